@@ -6,7 +6,7 @@ fn main() {
     let mut command = RobocopyCommandBuilder {
         source: Path::new("."),
         destination: Path::new("./copy"),
-        files: vec!["*"],
+        files: vec!["*".into()],
         only_copy_top_n_levels: Some(1),
         ..Default::default()
     }
@@ -2,14 +2,14 @@
 use std::{path::Path, io::{BufReader, BufRead}, process::{Command, Stdio}};
 
 use robocopyrs::{
-    RobocopyCommandBuilder, logging::LoggingOptions, exit_codes::OkExitCode,
+    RobocopyCommandBuilder, logging::LoggingOptions, exit_codes::OkExitCode, progress::parse_progress_line,
 };
 
 fn main() {
     let command = RobocopyCommandBuilder {
         source: Path::new("."),
         destination: Path::new("./copy"),
-        files: vec!["*"],
+        files: vec!["*".into()],
         only_copy_top_n_levels: Some(1),
         logging: Some(LoggingOptions {
             dont_log_class: true,
@@ -36,11 +36,15 @@ fn main() {
         if stdout_lines == 0 {
             break;
         }
-        println!("Read: {:?}", String::from_utf8_lossy(&buf));
+        let line = String::from_utf8_lossy(&buf);
+        match parse_progress_line(&line) {
+            Some(percentage) => println!("Progress: {percentage}%"),
+            None => println!("Read: {line:?}"),
+        }
         buf.clear();
     }
 
-    let exit_code = process.wait().expect("Command wasn't running").code().expect("Process terminated by signal") as i8;
+    let exit_code = process.wait().expect("Command wasn't running").code().expect("Process terminated by signal");
 
     match OkExitCode::try_from(exit_code) {
         Ok(success_code) => println!("Copy was successful: {success_code:?}"),
@@ -0,0 +1,197 @@
+//! Running several robocopy commands
+//!
+//! Robocopy copies a single source per run, a batch consolidates several sources into one destination
+//! by running one command per source, or runs independent copies concurrently.
+
+use std::{path::{Path, PathBuf}, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, thread};
+
+use crate::{Error, RobocopyCommandBuilder, exit_codes::OkExitCode};
+
+/// Several robocopy commands run in sequence
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RobocopyBatch<'a> {
+    /// The builders of the commands, in execution order
+    pub jobs: Vec<RobocopyCommandBuilder<'a>>,
+}
+
+impl<'a> RobocopyBatch<'a> {
+    /// Returns a batch copying the source of each builder into `destination`, replacing the builders' destination.
+    pub fn with_destination(destination: &'a Path, builders: impl IntoIterator<Item = RobocopyCommandBuilder<'a>>) -> Self {
+        RobocopyBatch {
+            jobs: builders.into_iter().map(|builder| RobocopyCommandBuilder { destination, ..builder }).collect(),
+        }
+    }
+
+    /// Adds a command to the end of the batch.
+    pub fn add(&mut self, builder: RobocopyCommandBuilder<'a>) -> &mut Self {
+        self.jobs.push(builder);
+        self
+    }
+
+    /// Executes the commands in sequence, returning the result of each command in the same order.
+    /// 
+    /// A failed command doesn't prevent the next ones from running,
+    /// see [worst_exit_code](RobocopyBatch::worst_exit_code) to summarize the results.
+    pub fn execute_all(&mut self) -> Vec<Result<OkExitCode, Error>> {
+        self.jobs.iter().map(|builder| builder.build().execute()).collect()
+    }
+
+    /// Executes the commands on up to `max_concurrent` threads, returning the result of each command in the same order as the jobs.
+    /// 
+    /// Commands whose destinations are the same, or nested in one another, are run one after the other in the batch order,
+    /// so that they don't write to the same files concurrently. A `max_concurrent` of 0 is treated as 1.
+    pub fn execute_parallel(&mut self, max_concurrent: usize) -> Vec<Result<OkExitCode, Error>> {
+        let groups = self.destination_groups();
+        let results: Mutex<Vec<Option<Result<OkExitCode, Error>>>> = Mutex::new(self.jobs.iter().map(|_| None).collect());
+        let next_group = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..max_concurrent.clamp(1, groups.len().max(1)) {
+                scope.spawn(|| {
+                    while let Some(group) = groups.get(next_group.fetch_add(1, Ordering::Relaxed)) {
+                        for &index in group {
+                            let result = self.jobs[index].build().execute();
+                            results.lock().unwrap()[index] = Some(result);
+                        }
+                    }
+                });
+            }
+        });
+
+        results.into_inner().unwrap().into_iter().map(|result| result.expect("every job is run")).collect()
+    }
+
+    /// Groups the indices of the jobs whose destinations overlap, in the batch order
+    fn destination_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(Vec<PathBuf>, Vec<usize>)> = Vec::new();
+
+        for (index, job) in self.jobs.iter().enumerate() {
+            let destination = normalize(job.destination);
+            let (mut overlapping, others): (Vec<_>, Vec<_>) = groups.into_iter()
+                .partition(|(destinations, _)| destinations.iter().any(|other| other.starts_with(&destination) || destination.starts_with(other)));
+
+            let mut merged = (vec![destination], vec![index]);
+            for (destinations, indices) in overlapping.drain(..) {
+                merged.0.extend(destinations);
+                merged.1.extend(indices);
+            }
+            merged.1.sort_unstable();
+
+            groups = others;
+            groups.push(merged);
+        }
+
+        let mut groups: Vec<Vec<usize>> = groups.into_iter().map(|(_, indices)| indices).collect();
+        groups.sort_unstable_by_key(|indices| indices[0]);
+        groups
+    }
+
+    /// Returns the first error of the results, or the highest exit code if every command succeeded.
+    /// 
+    /// Returns [OkExitCode::NO_CHANGE] if there's no result.
+    pub fn worst_exit_code(results: &[Result<OkExitCode, Error>]) -> Result<OkExitCode, &Error> {
+        results.iter().try_fold(OkExitCode::NO_CHANGE, |worst, result| match result {
+            Ok(code) => Ok(worst.max(*code)),
+            Err(err) => Err(err)
+        })
+    }
+}
+
+/// Returns the absolute path, lowercased as Windows paths are case-insensitive
+fn normalize(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    PathBuf::from(path.to_string_lossy().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{encoding::OutputEncoding, exit_codes::ErrExitCode, test_support::{FakeRobocopy, TempDir}};
+
+    fn job<'a>(fake: &'a FakeRobocopy, source: &'a Path, destination: &'a Path) -> RobocopyCommandBuilder<'a> {
+        RobocopyCommandBuilder {
+            robocopy_path: Some(fake.path()),
+            source,
+            destination,
+            output_encoding: Some(OutputEncoding::Utf8),
+            ..RobocopyCommandBuilder::default()
+        }
+    }
+
+    #[test]
+    fn merges_sources_into_one_destination() {
+        let dir = TempDir::new();
+        let first = dir.write("first/a.txt", "a").parent().unwrap().to_path_buf();
+        let second = dir.write("second/b.txt", "b").parent().unwrap().to_path_buf();
+        let destination = dir.path().join("merged");
+        let (first_fake, second_fake) = (FakeRobocopy::new("", 1), FakeRobocopy::new("", 3));
+
+        let mut batch = RobocopyBatch::with_destination(&destination, [
+            job(&first_fake, &first, Path::new("ignored")),
+            job(&second_fake, &second, Path::new("ignored")),
+        ]);
+        let results = batch.execute_all();
+
+        assert_eq!(results.iter().map(|result| *result.as_ref().unwrap()).collect::<Vec<_>>(), [OkExitCode::SOME_COPIES, OkExitCode::SOME_COPIES_EXTRA_FOUND]);
+        assert!(first_fake.calls()[0].starts_with(&format!("{} {}", first.display(), destination.display())), "{}", first_fake.calls()[0]);
+        assert!(second_fake.calls()[0].starts_with(&format!("{} {}", second.display(), destination.display())), "{}", second_fake.calls()[0]);
+        assert_eq!(RobocopyBatch::worst_exit_code(&results).unwrap(), OkExitCode::SOME_COPIES_EXTRA_FOUND);
+    }
+
+    #[test]
+    fn failed_job_doesnt_stop_the_batch() {
+        let dir = TempDir::new();
+        let (failing, succeeding) = (FakeRobocopy::new("", 8), FakeRobocopy::new("", 1));
+
+        let mut batch = RobocopyBatch::with_destination(dir.path(), [job(&failing, dir.path(), dir.path()), job(&succeeding, dir.path(), dir.path())]);
+        let results = batch.execute_all();
+
+        assert!(matches!(results[0], Err(Error::ExitCode(ErrExitCode::FAIL))));
+        assert_eq!(succeeding.calls().len(), 1);
+        assert!(matches!(RobocopyBatch::worst_exit_code(&results), Err(Error::ExitCode(ErrExitCode::FAIL))));
+    }
+
+    #[test]
+    fn worst_exit_code_of_no_result() {
+        assert_eq!(RobocopyBatch::worst_exit_code(&[]).unwrap(), OkExitCode::NO_CHANGE);
+    }
+
+    fn groups(destinations: &[&'static str]) -> Vec<Vec<usize>> {
+        RobocopyBatch {
+            jobs: destinations.iter().map(|destination| RobocopyCommandBuilder {
+                source: Path::new("source"),
+                destination: Path::new(destination),
+                ..RobocopyCommandBuilder::default()
+            }).collect(),
+        }.destination_groups()
+    }
+
+    #[test]
+    fn groups_disjoint_destinations_apart() {
+        assert_eq!(groups(&["a", "b", "c"]), [[0], [1], [2]]);
+        assert_eq!(groups(&["backup", "backup2"]), [[0], [1]]);
+    }
+
+    #[test]
+    fn groups_identical_and_nested_destinations() {
+        assert_eq!(groups(&["a", "b", "A"]), vec![vec![0, 2], vec![1]]);
+        assert_eq!(groups(&["a/nested", "b", "a"]), vec![vec![0, 2], vec![1]]);
+        assert_eq!(groups(&["a/x", "a/y", "a"]), vec![vec![0, 1, 2]]);
+    }
+
+    #[test]
+    fn parallel_results_follow_job_order() {
+        let dir = TempDir::new();
+        let fakes = [FakeRobocopy::new("", 0), FakeRobocopy::new("", 1), FakeRobocopy::new("", 2)];
+        let destinations = [dir.path().join("a"), dir.path().join("b"), dir.path().join("c")];
+
+        let mut batch = RobocopyBatch { jobs: fakes.iter().zip(&destinations).map(|(fake, destination)| job(fake, dir.path(), destination)).collect() };
+        let results = batch.execute_parallel(3);
+
+        assert_eq!(
+            results.iter().map(|result| *result.as_ref().unwrap()).collect::<Vec<_>>(),
+            [OkExitCode::NO_CHANGE, OkExitCode::SOME_COPIES, OkExitCode::EXTRA_FOUND]
+        );
+        assert!(fakes.iter().all(|fake| fake.calls().len() == 1));
+    }
+}
@@ -6,7 +6,7 @@ use std::{convert::TryFrom, fmt::Debug};
 /// Success exit codes
 /// 
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(i8)]
 pub enum OkExitCode{
     NO_CHANGE = 0,
@@ -17,12 +17,14 @@ pub enum OkExitCode{
     SOME_COPIES_MISMATCHES = 5,
     MISMATCHES_EXTRA_FOUND = 6,
     SOME_COPIES_MISMATCHES_EXTRA_FOUND = 7,
+    /// A failure exit code accepted as a success, see [RobocopyCommandBuilder::success_codes](crate::RobocopyCommandBuilder::success_codes)
+    ACCEPTED(i32),
 }
 
 /// Exit codes that include a failure.
 /// 
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(i8)]
 pub enum ErrExitCode{
     FAIL = 8,
@@ -34,14 +36,15 @@ pub enum ErrExitCode{
     FAIL_MISMATCHES_EXTRA_FOUND = 14,
     SOME_COPIES_FAIL_MISMATCHES_EXTRA_FOUND = 15,
     NO_CHANGE_FATAL_ERROR = 16,
-    INVALID_EXIT_CODE(i8)
+    /// An exit code robocopy isn't documented to return, with the raw value returned by the process
+    INVALID_EXIT_CODE(i32)
 }
 
 impl TryFrom<i8> for OkExitCode {
     type Error = ErrExitCode;
 
     fn try_from(n: i8) -> Result<Self, Self::Error> {
-        if n < 8 {
+        if (0..8).contains(&n) {
             Ok(
                 match n {
                     0 => OkExitCode::NO_CHANGE,
@@ -67,9 +70,40 @@ impl TryFrom<i8> for OkExitCode {
                     14 => ErrExitCode::FAIL_MISMATCHES_EXTRA_FOUND,
                     15 => ErrExitCode::SOME_COPIES_FAIL_MISMATCHES_EXTRA_FOUND,
                     16 => ErrExitCode::NO_CHANGE_FATAL_ERROR,
-                    c => ErrExitCode::INVALID_EXIT_CODE(c),
+                    c => ErrExitCode::INVALID_EXIT_CODE(c.into()),
                 }
             )
         }
     }
-}
\ No newline at end of file
+}
+
+impl TryFrom<i32> for OkExitCode {
+    type Error = ErrExitCode;
+
+    /// Converts the raw exit code of the process, codes that don't fit in an `i8` are kept in [ErrExitCode::INVALID_EXIT_CODE].
+    fn try_from(n: i32) -> Result<Self, Self::Error> {
+        match i8::try_from(n) {
+            Ok(n) => Self::try_from(n),
+            Err(_) => Err(ErrExitCode::INVALID_EXIT_CODE(n))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_documented_codes() {
+        assert_eq!(OkExitCode::try_from(1i32), Ok(OkExitCode::SOME_COPIES));
+        assert_eq!(OkExitCode::try_from(8i32), Err(ErrExitCode::FAIL));
+        assert_eq!(OkExitCode::try_from(16i8), Err(ErrExitCode::NO_CHANGE_FATAL_ERROR));
+    }
+
+    #[test]
+    fn keeps_raw_value_of_codes_out_of_range() {
+        assert_eq!(OkExitCode::try_from(300i32), Err(ErrExitCode::INVALID_EXIT_CODE(300)));
+        assert_eq!(OkExitCode::try_from(-1i32), Err(ErrExitCode::INVALID_EXIT_CODE(-1)));
+        assert_eq!(OkExitCode::try_from(17i8), Err(ErrExitCode::INVALID_EXIT_CODE(17)));
+    }
+}
@@ -2,13 +2,14 @@
 //! 
 //! All filters and exceptions are handled by the Filter struct
 
-use std::{convert::TryInto, ffi::OsString, ops::Add};
+use std::{borrow::Cow, convert::TryInto, ffi::OsString, fs, ops::Add, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
+use thiserror::Error;
 use crate::FileAttributes;
 use crate::MultipleVariant;
 
 /// Filters out files that match the variant
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileExclusionFilter {
     /// Excludes files for which any of the specified attributes are set. Corresponds to `/xa` option.
     Attributes(FileAttributes),
@@ -34,14 +35,15 @@ impl Add for FileExclusionFilter {
             Self::Attributes(attribs) => (Some(attribs), Vec::new(), [false; 4]),
             Self::PathOrName(path_or_name) => (None, path_or_name, [false; 4]),
             filter => {
-                let mut val = 2_u8.pow(filter.index_of().unwrap() as u32) + 2_u8; 
-                (None, Vec::new(), (0..6).map(|_| { val >>= 1; val == 1 }).collect::<Vec<bool>>().try_into().unwrap())
+                let mut filters = [false; 4];
+                filters[filter.index_of().unwrap()] = true;
+                (None, Vec::new(), filters)
             }
         };
 
         match rhs {
             Self::_MULTIPLE(attribs, mut path_or_name, filters) => {
-                result_filters = result_filters.iter().zip(filters.iter()).map(|(a, b)| *a && *b).collect::<Vec<bool>>().try_into().unwrap();
+                result_filters = result_filters.iter().zip(filters.iter()).map(|(a, b)| *a || *b).collect::<Vec<bool>>().try_into().unwrap();
                 if let Some(attribs) = attribs {
                     result_attribs = match result_attribs {
                         Some(res_attribs) => Some(attribs + res_attribs),
@@ -108,6 +110,27 @@ impl From<FileExclusionFilter> for Vec<OsString> {
 }
 
 impl FileExclusionFilter {
+    /// Excludes system and hidden files, such as `desktop.ini` and `Thumbs.db`.
+    /// 
+    /// Corresponds to `/xa:SH` option.
+    pub fn system_and_hidden() -> Self {
+        Self::Attributes(FileAttributes::SYSTEM + FileAttributes::HIDDEN)
+    }
+
+    /// Excludes read-only files.
+    /// 
+    /// Corresponds to `/xa:R` option.
+    pub fn read_only() -> Self {
+        Self::Attributes(FileAttributes::READ_ONLY)
+    }
+
+    /// Excludes temporary files.
+    /// 
+    /// Corresponds to `/xa:T` option.
+    pub fn temporary() -> Self {
+        Self::Attributes(FileAttributes::TEMPORARY)
+    }
+
     const VARIANTS: [Self; 4] = [
         Self::CHANGED,
         Self::OLDER,
@@ -118,6 +141,7 @@ impl FileExclusionFilter {
     fn index_of(&self) -> Option<usize>{
         match self {
             Self::CHANGED => Some(0),
+            Self::OLDER => Some(1),
             Self::NEWER => Some(2),
             Self::JUNCTION_POINTS => Some(3),
             _ => None,
@@ -127,7 +151,7 @@ impl FileExclusionFilter {
 
 /// Filters out directories that match the variant
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DirectoryExclusionFilter {
     /// Excludes directories that match the specified names and paths. Corresponds to `/xd` option.
     PathOrName(Vec<String>),
@@ -152,6 +176,8 @@ impl Add for DirectoryExclusionFilter {
             _ => junction_pts = true
         };
 
+        let result_path_or_name = Self::normalize(result_path_or_name);
+
         if junction_pts {
             Self::_BOTH(result_path_or_name)
         } else {
@@ -160,6 +186,21 @@ impl Add for DirectoryExclusionFilter {
     }
 }
 
+impl DirectoryExclusionFilter {
+    /// Uses backslashes as separators and removes duplicated paths, keeping the first occurrence.
+    /// 
+    /// Paths are compared case-insensitively, as Windows does.
+    fn normalize(paths: Vec<String>) -> Vec<String> {
+        let mut result: Vec<String> = Vec::with_capacity(paths.len());
+        for path in paths.into_iter().map(|path| path.replace('/', "\\")) {
+            if !result.iter().any(|existing| existing.to_lowercase() == path.to_lowercase()) {
+                result.push(path);
+            }
+        }
+        result
+    }
+}
+
 impl From<&DirectoryExclusionFilter> for Vec<OsString> {
     fn from(def: &DirectoryExclusionFilter) -> Self {
         let mut res = Vec::new();
@@ -193,7 +234,7 @@ impl MultipleVariant for DirectoryExclusionFilter {
 
 /// Filters out files and directories that match the variant
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FileAndDirectoryExclusionFilter {
     /// Excludes extra files and directories present in the destination but not the source.
     /// 
@@ -219,18 +260,8 @@ impl Add for FileAndDirectoryExclusionFilter {
     
     #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Self) -> Self::Output {
-        let mut result_filters = match self {
-            Self::_MULTIPLE(filters) => filters,
-            filter => {
-                let mut val = 2_u8.pow(filter.index_of().unwrap() as u32) + 2_u8; 
-                (0..6).map(|_| { val >>= 1; val == 1 }).collect::<Vec<bool>>().try_into().unwrap()
-            }
-        };
-
-        match rhs {
-            Self::_MULTIPLE(filters) => result_filters = result_filters.iter().zip(filters.iter()).map(|(a, b)| *a && *b).collect::<Vec<bool>>().try_into().unwrap(),
-            filter => result_filters[filter.index_of().unwrap()] = true
-        }
+        let mut result_filters = self.flags();
+        result_filters.iter_mut().zip(rhs.flags()).for_each(|(filter, added)| *filter |= added);
 
         Self::_MULTIPLE(result_filters)
     }
@@ -263,6 +294,10 @@ impl MultipleVariant for FileAndDirectoryExclusionFilter {
             attrib => vec![*attrib],
         }
     }
+
+    fn iter_variants(&self) -> impl Iterator<Item = Self> {
+        Self::VARIANTS.into_iter().zip(self.flags()).filter_map(|(variant, exists)| exists.then_some(variant))
+    }
 }
 
 impl FileAndDirectoryExclusionFilter {
@@ -280,10 +315,32 @@ impl FileAndDirectoryExclusionFilter {
             _ => None,
         }
     }
+
+    /// Returns the flags of the `_MULTIPLE` variant equivalent to this variant
+    fn flags(&self) -> [bool; 3] {
+        match self {
+            Self::_MULTIPLE(filters) => *filters,
+            filter => {
+                let mut filters = [false; 3];
+                filters[filter.index_of().unwrap()] = true;
+                filters
+            }
+        }
+    }
+
+    /// Returns true if this variant contains `variant`, or all the variants of `variant` if it is a `_MULTIPLE`.
+    pub fn contains(&self, variant: Self) -> bool {
+        self.flags().iter().zip(variant.flags().iter()).all(|(exists, wanted)| *exists || !*wanted)
+    }
 }
 
 /// Includes files despite the filters that match the variant
-#[derive(Debug, Copy, Clone)]
+/// 
+/// Robocopy skips same, tweaked and modified files by default, even without any [FileExclusionFilter].
+/// The exceptions override this default skipping, so they take effect on their own: `/is` alone recopies every identical file.
+/// They don't re-include files excluded by [FileExclusionFilter::CHANGED], [FileExclusionFilter::OLDER] or
+/// [FileExclusionFilter::NEWER], which match different classes of files.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FileExclusionFilterException {
     /// Include modified files (differing change times).
     /// 
@@ -305,18 +362,8 @@ impl Add for FileExclusionFilterException {
     
     #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Self) -> Self::Output {
-        let mut result_filters = match self {
-            Self::_MULTIPLE(filters) => filters,
-            filter => {
-                let mut val = 2_u8.pow(filter.index_of().unwrap() as u32) + 2_u8; 
-                (0..6).map(|_| { val >>= 1; val == 1 }).collect::<Vec<bool>>().try_into().unwrap()
-            }
-        };
-
-        match rhs {
-            Self::_MULTIPLE(filters) => result_filters = result_filters.iter().zip(filters.iter()).map(|(a, b)| *a && *b).collect::<Vec<bool>>().try_into().unwrap(),
-            filter => result_filters[filter.index_of().unwrap()] = true
-        }
+        let mut result_filters = self.flags();
+        result_filters.iter_mut().zip(rhs.flags()).for_each(|(filter, added)| *filter |= added);
 
         Self::_MULTIPLE(result_filters)
     }
@@ -349,6 +396,10 @@ impl MultipleVariant for FileExclusionFilterException {
             attrib => vec![*attrib],
         }
     }
+
+    fn iter_variants(&self) -> impl Iterator<Item = Self> {
+        Self::VARIANTS.into_iter().zip(self.flags()).filter_map(|(variant, exists)| exists.then_some(variant))
+    }
 }
 
 impl FileExclusionFilterException {
@@ -369,13 +420,40 @@ impl FileExclusionFilterException {
             _ => None,
         }
     }
+
+    /// Returns the flags of the `_MULTIPLE` variant equivalent to this variant
+    fn flags(&self) -> [bool; 3] {
+        match self {
+            Self::_MULTIPLE(filters) => *filters,
+            filter => {
+                let mut filters = [false; 3];
+                filters[filter.index_of().unwrap()] = true;
+                filters
+            }
+        }
+    }
+
+    /// Returns true if this variant contains `variant`, or all the variants of `variant` if it is a `_MULTIPLE`.
+    pub fn contains(&self, variant: Self) -> bool {
+        self.flags().iter().zip(variant.flags().iter()).all(|(exists, wanted)| *exists || !*wanted)
+    }
 }
 
 /// Handles all filter attributes supported by Robocopy
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Filter<'a> {
+    /// Copies only files for which the Archive attribute is set.
+    /// 
+    /// Unlike [handle_archive_and_reset](Filter::handle_archive_and_reset), the Archive attribute is left untouched
+    /// on the source files, so the same files will be copied again on the next run.
+    /// 
+    /// Corresponds to `/a` option.
+    pub copy_archive_only: bool,
     /// Copies only files for which the Archive attribute is set, and resets the Archive attribute.
     /// 
+    /// Resetting the attribute means the files won't be copied again until they are modified,
+    /// which makes it suitable for incremental backups.
+    /// 
     /// Corresponds to `/m` option.
     pub handle_archive_and_reset: bool,
 
@@ -401,31 +479,39 @@ pub struct Filter<'a> {
     /// 
     /// Corresponds to `/min` option.
     pub min_size: Option<u128>,
+    /// Specifies size bands to exclude, in bytes. Both bounds are inclusive, a `None` bound leaves the band open.
+    /// 
+    /// Robocopy can't exclude a band in a single run: use [size_band_filters](Filter::size_band_filters)
+    /// to get one filter per run. Building a command with excluded size bands fails validation.
+    pub excluded_size_bands: Vec<(Option<u128>, Option<u128>)>,
 
     /// Specifies the maximum file age (to exclude files older than n days or date).
     /// 
     /// Corresponds to `/maxage` option.
-    pub max_age: Option<&'a str>,
+    pub max_age: Option<Cow<'a, str>>,
     /// Specifies the minimum file age (exclude files newer than n days or date).
     /// 
     /// Corresponds to `/minage` option.
-    pub min_age: Option<&'a str>,
+    pub min_age: Option<Cow<'a, str>>,
 
     /// Specifies the maximum last access date (excludes files unused since n).
     /// 
     /// Corresponds to `/maxlad` option.
-    pub max_last_access_date: Option<&'a str>,
+    pub max_last_access_date: Option<Cow<'a, str>>,
     /// Specifies the minimum last access date (excludes files used since n) If n is less than 1900, n specifies the number of days.
     /// Otherwise, n specifies a date in the format YYYYMMDD.
     /// 
     /// Corresponds to `/minlad` option.
-    pub min_last_access_date: Option<&'a str>,
+    pub min_last_access_date: Option<Cow<'a, str>>,
 }
 
 impl<'a> From<&'a Filter<'a>> for Vec<OsString> {
     fn from(filter: &'a Filter<'a>) -> Self {
         let mut res = Vec::new();
         
+        if filter.copy_archive_only {
+            res.push(OsString::from("/a"));
+        }
         if filter.handle_archive_and_reset {
             res.push(OsString::from("/m"));
         }
@@ -454,17 +540,17 @@ impl<'a> From<&'a Filter<'a>> for Vec<OsString> {
             res.push(OsString::from(format!("/min:{}", min_size)));
         }
         
-        if let Some(max_age) = filter.max_age {
+        if let Some(max_age) = &filter.max_age {
             res.push(OsString::from(format!("/maxage:{}", max_age)));
         }
-        if let Some(min_age) = filter.min_age {
+        if let Some(min_age) = &filter.min_age {
             res.push(OsString::from(format!("/minage:{}", min_age)));
         }
 
-        if let Some(max_lad) = filter.max_last_access_date {
+        if let Some(max_lad) = &filter.max_last_access_date {
             res.push(OsString::from(format!("/maxlad:{}", max_lad)));
         }
-        if let Some(min_lad) = filter.min_last_access_date {
+        if let Some(min_lad) = &filter.min_last_access_date {
             res.push(OsString::from(format!("/minlad:{}", min_lad)));
         }
 
@@ -475,4 +561,640 @@ impl<'a> From<Filter<'a>> for Vec<OsString> {
     fn from(filter: Filter<'a>) -> Self {
         (&filter).into()
     }
-}
\ No newline at end of file
+}
+
+/// An inconsistency found while validating a [Filter]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    /// The minimum size is greater than the maximum size, no file can match
+    #[error("Minimum size ({min}) is greater than maximum size ({max})")]
+    InvertedSizeRange { min: u128, max: u128 },
+    /// The minimum age excludes every file kept by the maximum age
+    #[error("Minimum age ({min}) and maximum age ({max}) exclude every file")]
+    InvertedAgeRange { min: String, max: String },
+    /// The minimum last access date excludes every file kept by the maximum last access date
+    #[error("Minimum last access date ({min}) and maximum last access date ({max}) exclude every file")]
+    InvertedLastAccessRange { min: String, max: String },
+    /// The value is neither a number of days nor a date in the format YYYYMMDD
+    #[error("Invalid age or date: {0}")]
+    InvalidAge(String),
+    /// Two excluded size bands overlap, starting at the given size
+    #[error("Excluded size bands overlap at {0} bytes")]
+    OverlappingSizeBands(u128),
+}
+
+/// A file name or wildcard pattern (`*` and `?`), validated so that it can't be mistaken for an option
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern<'a>(Cow<'a, str>);
+
+impl<'a> Pattern<'a> {
+    /// Validates the pattern.
+    /// 
+    /// The pattern must not be empty nor start with `/`, and must not contain characters forbidden in
+    /// Windows file names other than the wildcards (`<`, `>`, `"`, `|` and control characters).
+    pub fn new(pattern: impl Into<Cow<'a, str>>) -> Result<Self, PatternError> {
+        let pattern = pattern.into();
+
+        if pattern.is_empty() {
+            return Err(PatternError::Empty);
+        }
+        if pattern.starts_with('/') {
+            return Err(PatternError::LooksLikeOption(pattern.into_owned()));
+        }
+        if let Some(character) = pattern.chars().find(|c| matches!(c, '<' | '>' | '"' | '|') || c.is_control()) {
+            return Err(PatternError::InvalidCharacter { pattern: pattern.into_owned(), character });
+        }
+
+        Ok(Self(pattern))
+    }
+
+    /// The pattern
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'a> From<Pattern<'a>> for Cow<'a, str> {
+    fn from(pattern: Pattern<'a>) -> Self {
+        pattern.0
+    }
+}
+
+/// An invalid file name or wildcard pattern
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// The pattern is empty
+    #[error("Empty file pattern")]
+    Empty,
+    /// The pattern starts with `/` and would be read as an option by robocopy
+    #[error("File pattern {0} would be read as an option")]
+    LooksLikeOption(String),
+    /// The pattern contains a character forbidden in file names
+    #[error("File pattern {pattern} contains the invalid character {character:?}")]
+    InvalidCharacter { pattern: String, character: char },
+}
+
+/// An age as understood by robocopy: a number of days if less than 1900, a YYYYMMDD date otherwise
+#[derive(Debug, Clone, Copy)]
+enum Age {
+    Days(u32),
+    Date(u32),
+}
+
+impl Age {
+    fn parse(age: &str) -> Result<Self, FilterError> {
+        match age.parse::<u32>() {
+            Ok(days) if days < 1900 => Ok(Self::Days(days)),
+            Ok(date) if (19000101..=99991231).contains(&date) => Ok(Self::Date(date)),
+            _ => Err(FilterError::InvalidAge(age.to_owned()))
+        }
+    }
+
+    /// Returns true if no file can be both older than `min` and younger than `max`.
+    /// 
+    /// A number of days can't be compared with a date without knowing the current date, so mixed bounds are accepted.
+    fn is_inverted(min: &str, max: &str) -> Result<bool, FilterError> {
+        Ok(match (Self::parse(min)?, Self::parse(max)?) {
+            (Self::Days(min), Self::Days(max)) => min > max,
+            (Self::Date(min), Self::Date(max)) => min < max,
+            _ => false
+        })
+    }
+}
+
+impl<'a> Filter<'a> {
+    /// Returns a [FilterBuilder] to create a validated filter
+    pub fn builder() -> FilterBuilder<'a> {
+        FilterBuilder::default()
+    }
+
+    /// Includes only files for which any of the specified attributes are set.
+    /// 
+    /// Corresponds to `/ia` option.
+    pub fn include_only_with_attributes(&mut self, attribs: FileAttributes) -> &mut Self {
+        self.include_only_files_with_any_of_these_attribs = Some(attribs);
+        self
+    }
+
+    /// Excludes files for which *any* of the specified attributes are set.
+    /// 
+    /// The attributes are merged with any existing file exclusion filter.
+    /// 
+    /// Corresponds to `/xa` option.
+    pub fn exclude_with_attributes(&mut self, attribs: FileAttributes) -> &mut Self {
+        self.add_file_exclusion_filter(FileExclusionFilter::Attributes(attribs))
+    }
+
+    /// Excludes the files with one of the extensions, by adding a `*.ext` wildcard to the excluded names for each extension.
+    /// 
+    /// Extensions may be given with or without the leading dot, such as `tmp` or `.tmp`.
+    /// The patterns are merged with any existing file exclusion filter.
+    /// 
+    /// Corresponds to `/xf` option.
+    pub fn exclude_extensions(&mut self, exts: &[&str]) -> &mut Self {
+        let patterns = exts.iter().map(|ext| format!("*.{}", ext.trim_start_matches('*').trim_start_matches('.'))).collect();
+        self.add_file_exclusion_filter(FileExclusionFilter::PathOrName(patterns))
+    }
+
+    /// Excludes zero-byte files by setting the minimum size to 1 byte.
+    /// 
+    /// Corresponds to `/min:1` option.
+    pub fn exclude_empty_files(&mut self) -> &mut Self {
+        self.min_size = Some(1);
+        self
+    }
+
+    /// Copies only files modified within `duration` of now, such as the last 24 hours.
+    /// 
+    /// Robocopy's maximum age excludes files *older* than the age, so this sets the maximum age, not the minimum.
+    /// Ages are counted in days, so the duration is rounded up to whole days and at least one day.
+    /// Robocopy reads ages of 1900 days or more as dates, so longer durations are set with [changed_since](Filter::changed_since).
+    /// 
+    /// Corresponds to `/maxage` option.
+    pub fn changed_within(&mut self, duration: Duration) -> &mut Self {
+        let days = duration.as_secs().div_ceil(86_400).max(1);
+        if days >= 1900 {
+            return self.changed_since(SystemTime::now().checked_sub(duration).unwrap_or(UNIX_EPOCH));
+        }
+        self.max_age = Some(Cow::Owned(days.to_string()));
+        self
+    }
+
+    /// Copies only files modified since `date`.
+    /// 
+    /// Like [changed_within](Filter::changed_within), this sets the maximum age, to the UTC date of the day before `date`
+    /// (YYYYMMDD) since robocopy compares dates in the local time zone. Robocopy only handles dates with a day precision,
+    /// so files modified up to two days before `date` are copied too.
+    /// 
+    /// Corresponds to `/maxage` option.
+    pub fn changed_since(&mut self, date: SystemTime) -> &mut Self {
+        self.max_age = Some(Cow::Owned(robocopy_date_since(date)));
+        self
+    }
+
+    pub(crate) fn add_file_exclusion_filter(&mut self, filter: FileExclusionFilter) -> &mut Self {
+        self.file_exclusion_filter = Some(match self.file_exclusion_filter.take() {
+            Some(existing) => existing + filter,
+            None => filter
+        });
+        self
+    }
+
+    /// Returns one filter per size range left by the excluded size bands, running robocopy once per filter
+    /// copies every file outside of the excluded bands.
+    /// 
+    /// The returned filters have no excluded size band. If there is no excluded band, the filter itself is returned.
+    pub fn size_band_filters(&self) -> Vec<Filter<'a>> {
+        let upper = self.max_size.unwrap_or(u128::MAX);
+        let mut lower = Some(self.min_size.unwrap_or(0));
+        let mut ranges = Vec::new();
+
+        for (min, max) in self.sorted_size_bands() {
+            let Some(start) = lower else { break };
+            if min > start {
+                ranges.push((start, (min - 1).min(upper)));
+            }
+            lower = max.checked_add(1).map(|next| next.max(start));
+        }
+        if let Some(start) = lower {
+            ranges.push((start, upper));
+        }
+
+        ranges.into_iter()
+            .filter(|(min, max)| min <= max)
+            .map(|(min, max)| Filter {
+                min_size: Some(min).filter(|min| *min > 0 || self.min_size.is_some()),
+                max_size: Some(max).filter(|max| *max < u128::MAX || self.max_size.is_some()),
+                excluded_size_bands: Vec::new(),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Returns the excluded size bands with their bounds resolved, sorted by lower bound
+    fn sorted_size_bands(&self) -> Vec<(u128, u128)> {
+        let mut bands: Vec<(u128, u128)> = self.excluded_size_bands.iter()
+            .map(|(min, max)| (min.unwrap_or(0), max.unwrap_or(u128::MAX)))
+            .collect();
+        bands.sort();
+        bands
+    }
+
+    /// Checks that the size, age and last access date bounds don't exclude every file.
+    pub fn validate(&self) -> Result<(), FilterError> {
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) {
+            if min > max {
+                return Err(FilterError::InvertedSizeRange { min, max });
+            }
+        }
+
+        let bands = self.sorted_size_bands();
+        for &(min, max) in &bands {
+            if min > max {
+                return Err(FilterError::InvertedSizeRange { min, max });
+            }
+        }
+        for pair in bands.windows(2) {
+            if pair[1].0 <= pair[0].1 {
+                return Err(FilterError::OverlappingSizeBands(pair[1].0));
+            }
+        }
+
+        for age in [&self.min_age, &self.max_age, &self.min_last_access_date, &self.max_last_access_date].into_iter().flatten() {
+            Age::parse(age)?;
+        }
+
+        if let (Some(min), Some(max)) = (&self.min_age, &self.max_age) {
+            if Age::is_inverted(min, max)? {
+                return Err(FilterError::InvertedAgeRange { min: min.to_string(), max: max.to_string() });
+            }
+        }
+        if let (Some(min), Some(max)) = (&self.min_last_access_date, &self.max_last_access_date) {
+            if Age::is_inverted(min, max)? {
+                return Err(FilterError::InvertedLastAccessRange { min: min.to_string(), max: max.to_string() });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A builder for [Filter] that validates the filter on build
+/// 
+/// ```
+/// use robocopyrs::filter::Filter;
+/// 
+/// let filter = Filter::builder()
+///     .min_size(1024)
+///     .max_age("30")
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilterBuilder<'a> {
+    filter: Filter<'a>,
+}
+
+impl<'a> FilterBuilder<'a> {
+    /// Copies only files for which the Archive attribute is set.
+    /// 
+    /// Corresponds to `/a` option.
+    pub fn copy_archive_only(mut self) -> Self {
+        self.filter.copy_archive_only = true;
+        self
+    }
+
+    /// Copies only files for which the Archive attribute is set, and resets the Archive attribute.
+    /// 
+    /// Corresponds to `/m` option.
+    pub fn handle_archive_and_reset(mut self) -> Self {
+        self.filter.handle_archive_and_reset = true;
+        self
+    }
+
+    /// Includes only files for which any of the specified attributes are set.
+    /// 
+    /// Corresponds to `/ia` option.
+    pub fn include_only_files_with_any_of_these_attribs(mut self, attribs: FileAttributes) -> Self {
+        self.filter.include_only_files_with_any_of_these_attribs = Some(attribs);
+        self
+    }
+
+    /// Filters out which files to copy.
+    pub fn file_exclusion_filter(mut self, filter: FileExclusionFilter) -> Self {
+        self.filter.file_exclusion_filter = Some(filter);
+        self
+    }
+
+    /// Filters out which directories to copy.
+    pub fn directory_exclusion_filter(mut self, filter: DirectoryExclusionFilter) -> Self {
+        self.filter.directory_exclusion_filter = Some(filter);
+        self
+    }
+
+    /// Filters out which files and directories to copy.
+    pub fn file_and_directory_exclusion_filter(mut self, filter: FileAndDirectoryExclusionFilter) -> Self {
+        self.filter.file_and_directory_exclusion_filter = Some(filter);
+        self
+    }
+
+    /// Includes files despite the filters.
+    pub fn file_exclusion_filter_exceptions(mut self, exceptions: FileExclusionFilterException) -> Self {
+        self.filter.file_exclusion_filter_exceptions = Some(exceptions);
+        self
+    }
+
+    /// Excludes files bigger than `n` bytes.
+    /// 
+    /// Corresponds to `/max` option.
+    pub fn max_size(mut self, n: u128) -> Self {
+        self.filter.max_size = Some(n);
+        self
+    }
+
+    /// Excludes files smaller than `n` bytes.
+    /// 
+    /// Corresponds to `/min` option.
+    pub fn min_size(mut self, n: u128) -> Self {
+        self.filter.min_size = Some(n);
+        self
+    }
+
+    /// Excludes files older than `n` days or date.
+    /// 
+    /// Corresponds to `/maxage` option.
+    pub fn max_age(mut self, n: impl Into<Cow<'a, str>>) -> Self {
+        self.filter.max_age = Some(n.into());
+        self
+    }
+
+    /// Excludes files newer than `n` days or date.
+    /// 
+    /// Corresponds to `/minage` option.
+    pub fn min_age(mut self, n: impl Into<Cow<'a, str>>) -> Self {
+        self.filter.min_age = Some(n.into());
+        self
+    }
+
+    /// Excludes files unused since `n`.
+    /// 
+    /// Corresponds to `/maxlad` option.
+    pub fn max_last_access_date(mut self, n: impl Into<Cow<'a, str>>) -> Self {
+        self.filter.max_last_access_date = Some(n.into());
+        self
+    }
+
+    /// Excludes files used since `n`.
+    /// 
+    /// Corresponds to `/minlad` option.
+    pub fn min_last_access_date(mut self, n: impl Into<Cow<'a, str>>) -> Self {
+        self.filter.min_last_access_date = Some(n.into());
+        self
+    }
+
+    /// Copies only files modified since the last modification of the `marker` file.
+    /// 
+    /// This sets the maximum age to the UTC date of the day before the marker was modified, as robocopy compares dates
+    /// in the local time zone which can be a day behind UTC. Robocopy only handles dates with a day precision,
+    /// so files modified on the same day as the marker, or on the day before, are copied too.
+    /// Touch the marker after a successful copy to implement incremental backups.
+    /// 
+    /// If the marker doesn't exist or its modification time can't be read, the filter is left unchanged and every file is copied.
+    /// 
+    /// Corresponds to `/maxage` option.
+    pub fn changed_since_marker(mut self, marker: &Path) -> Self {
+        if let Ok(modified) = fs::metadata(marker).and_then(|metadata| metadata.modified()) {
+            self.filter.max_age = Some(Cow::Owned(robocopy_date_since(modified)));
+        }
+        self
+    }
+
+    /// Validates and returns the filter.
+    pub fn build(self) -> Result<Filter<'a>, FilterError> {
+        self.filter.validate()?;
+        Ok(self.filter)
+    }
+}
+
+/// Formats the date of the day before `time` (YYYYMMDD, in UTC), to be used as a maximum age keeping every file modified since `time`.
+/// 
+/// Robocopy compares dates in the local time zone, which can be up to a day behind UTC.
+/// The UTC date of the day before is never after the local date of `time`, so no file modified since `time` is excluded,
+/// at the cost of copying the files of up to two more days.
+pub(crate) fn robocopy_date_since(time: SystemTime) -> String {
+    robocopy_date(time.checked_sub(Duration::from_secs(86_400)).unwrap_or(UNIX_EPOCH))
+}
+
+/// Formats a time as a robocopy date (YYYYMMDD, in UTC)
+fn robocopy_date(time: SystemTime) -> String {
+    let days = time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() / 86_400).unwrap_or(0) as i64;
+
+    // Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(filter: &Filter) -> Vec<String> {
+        Vec::<OsString>::from(filter).into_iter().map(|arg| arg.into_string().unwrap()).collect()
+    }
+
+    #[test]
+    fn archive_options_are_independent() {
+        let archive_only = Filter { copy_archive_only: true, ..Filter::default() };
+        let archive_and_reset = Filter { handle_archive_and_reset: true, ..Filter::default() };
+        let both = Filter { copy_archive_only: true, handle_archive_and_reset: true, ..Filter::default() };
+
+        assert_eq!(args(&archive_only), ["/a"]);
+        assert_eq!(args(&archive_and_reset), ["/m"]);
+        assert_eq!(args(&both), ["/a", "/m"]);
+    }
+
+    #[test]
+    fn builder_rejects_inverted_size_range() {
+        assert_eq!(
+            Filter::builder().min_size(2048).max_size(1024).build().unwrap_err(),
+            FilterError::InvertedSizeRange { min: 2048, max: 1024 }
+        );
+        assert!(Filter::builder().min_size(1024).max_size(1024).build().is_ok());
+    }
+
+    #[test]
+    fn builder_rejects_inverted_age_range() {
+        // Files must be older than 30 days and younger than 7 days
+        assert_eq!(
+            Filter::builder().min_age("30").max_age("7").build().unwrap_err(),
+            FilterError::InvertedAgeRange { min: String::from("30"), max: String::from("7") }
+        );
+        // Files must be older than 2024-01-01 and newer than 2024-06-01
+        assert_eq!(
+            Filter::builder().min_age("20240101").max_age("20240601").build().unwrap_err(),
+            FilterError::InvertedAgeRange { min: String::from("20240101"), max: String::from("20240601") }
+        );
+        assert!(Filter::builder().min_age("7").max_age("30").build().is_ok());
+        assert_eq!(Filter::builder().max_age("yesterday").build().unwrap_err(), FilterError::InvalidAge(String::from("yesterday")));
+    }
+
+    #[test]
+    fn exclude_with_attributes_emits_xa() {
+        let mut filter = Filter::default();
+        filter.exclude_with_attributes(FileAttributes::READ_ONLY + FileAttributes::TEMPORARY);
+        assert_eq!(args(&filter), ["/xa:RT"]);
+
+        filter.exclude_with_attributes(FileAttributes::HIDDEN);
+        assert_eq!(args(&filter), ["/xa:RHT"]);
+    }
+
+    #[test]
+    fn include_only_with_attributes_emits_ia() {
+        let mut filter = Filter::default();
+        filter.include_only_with_attributes(FileAttributes::ARCHIVE + FileAttributes::SYSTEM);
+        assert_eq!(args(&filter), ["/ia:AS"]);
+    }
+
+    #[test]
+    fn merged_directory_exclusions_emit_shared_path_once() {
+        let merged = DirectoryExclusionFilter::PathOrName(vec![String::from("build/cache"), String::from("node_modules")])
+            + DirectoryExclusionFilter::PathOrName(vec![String::from("Build\\Cache"), String::from("target")]);
+
+        assert_eq!(Vec::<OsString>::from(&merged), ["/xd", "build\\cache", "node_modules", "target"]);
+
+        let with_junctions = merged + DirectoryExclusionFilter::JUNCTION_POINTS;
+        assert_eq!(Vec::<OsString>::from(&with_junctions), ["/xjd", "/xd", "build\\cache", "node_modules", "target"]);
+    }
+
+    #[test]
+    fn marker_date_is_the_day_before_in_utc() {
+        let dir = crate::test_support::TempDir::new();
+        let marker = dir.write("marker", "");
+        // 2024-03-15 00:30 UTC, still 2024-03-14 west of UTC
+        let modified = UNIX_EPOCH + Duration::from_secs(1_710_462_600);
+        fs::File::options().write(true).open(&marker).unwrap().set_modified(modified).unwrap();
+
+        let filter = Filter::builder().changed_since_marker(&marker).build().unwrap();
+        assert_eq!(filter.max_age.as_deref(), Some("20240314"));
+        assert_eq!(robocopy_date(modified), "20240315");
+    }
+
+    #[test]
+    fn missing_marker_leaves_filter_unchanged() {
+        let dir = crate::test_support::TempDir::new();
+
+        let filter = Filter::builder().changed_since_marker(&dir.path().join("missing")).build().unwrap();
+        assert!(args(&filter).is_empty());
+    }
+
+    #[test]
+    fn exclude_empty_files_emits_min_1() {
+        let mut filter = Filter::default();
+        filter.exclude_empty_files();
+        assert_eq!(args(&filter), ["/min:1"]);
+    }
+
+    #[test]
+    fn rejects_overlapping_size_bands() {
+        let filter = Filter { excluded_size_bands: vec![(Some(100), Some(200)), (Some(150), Some(300))], ..Filter::default() };
+        assert_eq!(filter.validate(), Err(FilterError::OverlappingSizeBands(150)));
+
+        let adjacent = Filter { excluded_size_bands: vec![(Some(100), Some(200)), (Some(201), None)], ..Filter::default() };
+        assert_eq!(adjacent.validate(), Ok(()));
+
+        let inverted = Filter { excluded_size_bands: vec![(Some(200), Some(100))], ..Filter::default() };
+        assert_eq!(inverted.validate(), Err(FilterError::InvertedSizeRange { min: 200, max: 100 }));
+    }
+
+    #[test]
+    fn size_band_filters_cover_the_gaps() {
+        let bounds = |filter: &Filter| (filter.min_size, filter.max_size);
+
+        let filter = Filter { excluded_size_bands: vec![(Some(1000), Some(1999)), (Some(100), Some(199))], ..Filter::default() };
+        let filters = filter.size_band_filters();
+        assert_eq!(filters.iter().map(bounds).collect::<Vec<_>>(), [(None, Some(99)), (Some(200), Some(999)), (Some(2000), None)]);
+        assert!(filters.iter().all(|filter| filter.excluded_size_bands.is_empty()));
+
+        let bounded = Filter { min_size: Some(10), max_size: Some(5000), excluded_size_bands: vec![(None, Some(49)), (Some(4000), None)], ..Filter::default() };
+        assert_eq!(bounded.size_band_filters().iter().map(bounds).collect::<Vec<_>>(), [(Some(50), Some(3999))]);
+
+        let no_band = Filter { min_size: Some(1), ..Filter::default() };
+        assert_eq!(no_band.size_band_filters().iter().map(bounds).collect::<Vec<_>>(), [(Some(1), None)]);
+    }
+
+    #[test]
+    fn exclusion_flags_contain() {
+        let filters = FileAndDirectoryExclusionFilter::EXTRA + FileAndDirectoryExclusionFilter::JUNCTION_POINTS;
+        assert!(filters.contains(FileAndDirectoryExclusionFilter::EXTRA));
+        assert!(!filters.contains(FileAndDirectoryExclusionFilter::LONELY));
+
+        let exceptions = FileExclusionFilterException::SAME + FileExclusionFilterException::TWEAKED;
+        assert!(exceptions.contains(FileExclusionFilterException::TWEAKED));
+        assert!(!exceptions.contains(FileExclusionFilterException::MODIFIED));
+    }
+
+    #[test]
+    fn exception_without_exclusion_filter_is_valid() {
+        let filter = Filter::builder()
+            .file_exclusion_filter_exceptions(FileExclusionFilterException::SAME)
+            .build()
+            .unwrap();
+
+        assert_eq!(args(&filter), ["/is"]);
+    }
+
+    #[test]
+    fn changed_within_sets_max_age_in_days() {
+        let mut filter = Filter::default();
+
+        filter.changed_within(Duration::from_secs(24 * 3_600));
+        assert_eq!(args(&filter), ["/maxage:1"]);
+        filter.changed_within(Duration::from_secs(25 * 3_600));
+        assert_eq!(args(&filter), ["/maxage:2"]);
+        filter.changed_within(Duration::ZERO);
+        assert_eq!(args(&filter), ["/maxage:1"]);
+        filter.changed_within(Duration::from_secs(1_899 * 86_400));
+        assert_eq!(args(&filter), ["/maxage:1899"]);
+    }
+
+    #[test]
+    fn changed_within_long_durations_sets_a_date() {
+        let mut filter = Filter::default();
+        filter.changed_within(Duration::from_secs(3_000 * 86_400));
+
+        let age = filter.max_age.as_deref().unwrap();
+        assert_eq!(age.len(), 8, "{}", age);
+        assert!(matches!(Age::parse(age), Ok(Age::Date(_))), "{}", age);
+        assert_eq!(filter.min_age, None);
+    }
+
+    #[test]
+    fn changed_since_sets_the_day_before() {
+        let mut filter = Filter::default();
+        filter.changed_since(UNIX_EPOCH + Duration::from_secs(1_710_462_600));
+
+        assert_eq!(args(&filter), ["/maxage:20240314"]);
+    }
+
+    #[test]
+    fn exclude_extensions_keeps_existing_exclusions() {
+        let mut filter = Filter {
+            file_exclusion_filter: Some(FileExclusionFilter::PathOrName(vec![String::from("*.bak")]) + FileExclusionFilter::OLDER),
+            ..Filter::default()
+        };
+        filter.exclude_extensions(&["tmp", ".log"]);
+
+        assert_eq!(args(&filter), ["/xo", "/xf", "*.bak", "*.tmp", "*.log"]);
+
+        let mut empty = Filter::default();
+        empty.exclude_extensions(&["tmp"]);
+        assert_eq!(args(&empty), ["/xf", "*.tmp"]);
+    }
+
+    #[test]
+    fn pattern_rejects_options_and_invalid_characters() {
+        assert_eq!(Pattern::new("/e"), Err(PatternError::LooksLikeOption(String::from("/e"))));
+        assert_eq!(Pattern::new(""), Err(PatternError::Empty));
+        assert_eq!(Pattern::new("a|b"), Err(PatternError::InvalidCharacter { pattern: String::from("a|b"), character: '|' }));
+        assert_eq!(Pattern::new("*.rs").unwrap().as_str(), "*.rs");
+        assert_eq!(Pattern::new("file?.txt").unwrap().as_str(), "file?.txt");
+    }
+
+    #[test]
+    fn attribute_presets_serialize_to_xa() {
+        let with = |filter| Filter { file_exclusion_filter: Some(filter), ..Filter::default() };
+
+        assert_eq!(args(&with(FileExclusionFilter::system_and_hidden())), ["/xa:SH"]);
+        assert_eq!(args(&with(FileExclusionFilter::read_only())), ["/xa:R"]);
+        assert_eq!(args(&with(FileExclusionFilter::system_and_hidden() + FileExclusionFilter::temporary())), ["/xa:SHT"]);
+    }
+}
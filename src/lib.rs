@@ -27,27 +27,50 @@ pub mod properties;
 pub mod performance;
 pub mod logging;
 pub mod exit_codes;
+pub mod report;
+pub mod progress;
+pub mod encoding;
+pub mod batch;
+pub mod unc;
+#[cfg(feature = "verify")]
+pub mod verify;
 
-use std::io;
-use std::{convert::TryInto, ffi::OsString, ops::Add, path::Path, process::Command};
+#[cfg(test)]
+mod test_support;
+
+use std::io::{self, BufRead, BufReader};
+use std::time::{Instant, SystemTime};
+use std::{ffi::{OsStr, OsString}, ops::{Add, Sub}, path::{Path, PathBuf}, process::{Command, Stdio}};
 use std::fmt::Debug;
+use std::borrow::Cow;
 use thiserror::Error;
 
 use exit_codes::{OkExitCode, ErrExitCode};
-use filter::Filter;
-use performance::{PerformanceOptions, RetrySettings};
-use logging::LoggingOptions;
+use filter::{Filter, FilterError, FileExclusionFilter, Pattern, PatternError};
+use performance::{PerformanceChoice, PerformanceOptions, PerformanceError, RetrySettings};
+use logging::{LoggingOptions, LoggingError};
 use properties::{FileProperties, DirectoryProperties};
+use report::{CopyResult, DestructiveSummary, Estimate, FileClass, FileRecord, RobocopyReport, SyncDiff};
+use progress::ProgressEvent;
+use encoding::OutputEncoding;
+use unc::{UncConnection, UncCredentials};
 
 /// For enums that allow for multiple variants to be 
 /// joined into a single variant
 pub trait MultipleVariant: Sized + Add<Self> {
     /// get each variant in a multiple-variant
     fn single_variants(&self) -> Vec<Self>;
+
+    /// iterate over each variant in a multiple-variant
+    /// 
+    /// Bit-set variants are iterated without allocating.
+    fn iter_variants(&self) -> impl Iterator<Item = Self> {
+        self.single_variants().into_iter()
+    }
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FileAttributes {
     READ_ONLY,
     ARCHIVE,
@@ -65,18 +88,20 @@ impl Add for FileAttributes {
     
     #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Self) -> Self::Output {
-        let mut result_attribs = match self {
-            Self::_MULTIPLE(attribs) => attribs,
-            attrib => {
-                let mut val = 2_u8.pow(attrib.index_of().unwrap() as u32) * 2_u8; 
-                (0..6).map(|_| { val >>= 1; val == 1 }).collect::<Vec<bool>>().try_into().unwrap()
-            }
-        };
+        let mut result_attribs = self.flags();
+        result_attribs.iter_mut().zip(rhs.flags()).for_each(|(attrib, added)| *attrib |= added);
 
-        match rhs {
-            Self::_MULTIPLE(attribs) => result_attribs = result_attribs.iter().zip(attribs.iter()).map(|(a, b)| *a && *b).collect::<Vec<bool>>().try_into().unwrap(),
-            attrib => result_attribs[attrib.index_of().unwrap()] = true
-        }
+        Self::_MULTIPLE(result_attribs)
+    }
+}
+
+impl Sub for FileAttributes {
+    type Output = Self;
+    
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: Self) -> Self::Output {
+        let mut result_attribs = self.flags();
+        result_attribs.iter_mut().zip(rhs.flags()).for_each(|(attrib, removed)| *attrib &= !removed);
 
         Self::_MULTIPLE(result_attribs)
     }
@@ -116,6 +141,10 @@ impl MultipleVariant for FileAttributes {
             attrib => vec![*attrib],
         }
     }
+
+    fn iter_variants(&self) -> impl Iterator<Item = Self> {
+        Self::VARIANTS.into_iter().zip(self.flags()).filter_map(|(variant, exists)| exists.then_some(variant))
+    }
 }
 
 impl FileAttributes {
@@ -144,6 +173,23 @@ impl FileAttributes {
         }
     }
 
+    /// Returns the flags of the `_MULTIPLE` variant equivalent to this variant
+    fn flags(&self) -> [bool; 8] {
+        match self {
+            Self::_MULTIPLE(attribs) => *attribs,
+            attrib => {
+                let mut attribs = [false; 8];
+                attribs[attrib.index_of().unwrap()] = true;
+                attribs
+            }
+        }
+    }
+
+    /// Returns true if this variant contains `variant`, or all the variants of `variant` if it is a `_MULTIPLE`.
+    pub fn contains(&self, variant: Self) -> bool {
+        self.flags().iter().zip(variant.flags().iter()).all(|(exists, wanted)| *exists || !*wanted)
+    }
+
     /// Returns a variant containing all available file attributes.
     #[allow(unused)]
     pub fn all() -> Self {
@@ -155,11 +201,64 @@ impl FileAttributes {
     pub fn none() -> Self {
         Self::_MULTIPLE([false; 8])
     }
+
+    /// Returns the number of file attributes in the variant.
+    pub fn count(&self) -> usize {
+        self.flags().iter().filter(|exists| **exists).count()
+    }
+
+    /// Returns true if the variant contains no file attributes.
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Collapses a `_MULTIPLE` containing a single file attribute into its simple variant.
+    /// 
+    /// Empty variants and variants with several flags are returned as a `_MULTIPLE`, the empty one being equal to [none](Self::none).
+    pub fn canonicalize(self) -> Self {
+        let mut variants = self.iter_variants();
+        match (variants.next(), variants.next()) {
+            (Some(variant), None) => variant,
+            _ => Self::_MULTIPLE(self.flags())
+        }
+    }
+}
+
+/// Which subdirectories to copy
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SubdirMode {
+    /// Copies only the files of the source directory, robocopy's default.
+    #[default]
+    NONE,
+    /// Copies subdirectories, excluding empty ones.
+    /// 
+    /// Corresponds to `/s` option.
+    NON_EMPTY,
+    /// Copies subdirectories, including empty ones.
+    /// 
+    /// Corresponds to `/e` option.
+    INCLUDING_EMPTY,
+}
+
+impl From<&SubdirMode> for Vec<OsString> {
+    fn from(sm: &SubdirMode) -> Self {
+        match sm {
+            SubdirMode::NONE => Vec::new(),
+            SubdirMode::NON_EMPTY => vec![OsString::from("/s")],
+            SubdirMode::INCLUDING_EMPTY => vec![OsString::from("/e")],
+        }
+    }
+}
+impl From<SubdirMode> for Vec<OsString> {
+    fn from(sm: SubdirMode) -> Self {
+        (&sm).into()
+    }
 }
 
 /// A copy strategy
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CopyMode {
     /// Copies files in restartable mode.
     /// 
@@ -196,7 +295,7 @@ impl From<CopyMode> for OsString {
 
 /// The move strategy
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Move {
     /// Moves files, and deletes them from the source after they're copied.
     /// 
@@ -223,7 +322,9 @@ impl From<Move> for OsString {
 }
 
 /// What attributes to add or remove from copied files.
-#[derive(Debug, Copy, Clone)]
+/// 
+/// Use [PostCopyActions::new] to add and remove attributes at the same time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PostCopyActions {
     /// Adds the specified attributes to copied files.
     /// 
@@ -233,56 +334,78 @@ pub enum PostCopyActions {
     /// 
     /// Corresponds to `/a-` option.
     RmvAttribsFromFiles(FileAttributes),
-    _MULTIPLE(FileAttributes, FileAttributes)
+    #[doc(hidden)]
+    _MULTIPLE(AddedAndRemovedAttribs)
+}
+
+/// The attributes added and removed by a [PostCopyActions] doing both, only built by this crate
+#[doc(hidden)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AddedAndRemovedAttribs {
+    add: FileAttributes,
+    remove: FileAttributes,
 }
 
 impl Add for PostCopyActions {
     type Output = Self;
 
+    /// Merges the actions. If no attribute is added nor removed, the result produces no argument.
     fn add(self, rhs: Self) -> Self::Output {
-        let (mut add_attribs, mut rmv_attribs) = match self {
-            Self::_MULTIPLE(add, rmv) => (Some(add), Some(rmv)),
-            Self::AddAttribsToFiles(attribs) => (None, Some(attribs)),
-            Self::RmvAttribsFromFiles(attribs) => (Some(attribs), None)
+        self.merge(rhs).unwrap_or(Self::_MULTIPLE(AddedAndRemovedAttribs { add: FileAttributes::none(), remove: FileAttributes::none() }))
+    }
+}
+
+impl PostCopyActions {
+    /// Returns the actions adding `add` and removing `remove` from copied files,
+    /// or `None` if both sets of attributes are empty.
+    pub fn new(add: FileAttributes, remove: FileAttributes) -> Option<Self> {
+        Self::from_parts(Some(add), Some(remove))
+    }
+
+    /// Merges the actions, returning `None` if no attribute is added nor removed.
+    pub fn merge(self, rhs: Self) -> Option<Self> {
+        let (add, rmv) = self.parts();
+        let (rhs_add, rhs_rmv) = rhs.parts();
+
+        Self::from_parts(union(add, rhs_add), union(rmv, rhs_rmv))
+    }
+
+    /// Returns the attributes to add and to remove, `None` if the set is empty
+    fn parts(self) -> (Option<FileAttributes>, Option<FileAttributes>) {
+        let (add, rmv) = match self {
+            Self::AddAttribsToFiles(add) => (Some(add), None),
+            Self::RmvAttribsFromFiles(rmv) => (None, Some(rmv)),
+            Self::_MULTIPLE(AddedAndRemovedAttribs { add, remove }) => (Some(add), Some(remove))
         };
+        let non_empty = |attribs: &FileAttributes| !attribs.is_empty();
 
-        match rhs {
-            Self::_MULTIPLE(add, rmv) => {
-                if let Some(attribs) = add_attribs {
-                    add_attribs = Some(attribs + add);
-                }
-                if let Some(attribs) = rmv_attribs{
-                    rmv_attribs = Some(attribs + rmv);
-                }
-            },
-            Self::AddAttribsToFiles(add) => {
-                if let Some(attribs) = add_attribs {
-                    add_attribs = Some(attribs + add);
-                }
-            },
-            Self::RmvAttribsFromFiles(rmv) => {
-                if let Some(attribs) = rmv_attribs{
-                    rmv_attribs = Some(attribs + rmv);
-                }
-            }
-        }
+        (add.filter(non_empty), rmv.filter(non_empty))
+    }
+
+    fn from_parts(add: Option<FileAttributes>, rmv: Option<FileAttributes>) -> Option<Self> {
+        let non_empty = |attribs: &FileAttributes| !attribs.is_empty();
 
-        match (add_attribs, rmv_attribs) {
-            (Some(add), Some(rmv)) => Self::_MULTIPLE(add, rmv),
-            (None, Some(rmv)) => Self::RmvAttribsFromFiles(rmv),
-            (Some(add), None) => Self::AddAttribsToFiles(add),
-            (None, None) => panic!("use default rather than PostCopyActions::_MULTIPLE(FileAttributes::none(), FileAttributes::none())")
+        match (add.filter(non_empty), rmv.filter(non_empty)) {
+            (Some(add), Some(remove)) => Some(Self::_MULTIPLE(AddedAndRemovedAttribs { add, remove })),
+            (Some(add), None) => Some(Self::AddAttribsToFiles(add)),
+            (None, Some(rmv)) => Some(Self::RmvAttribsFromFiles(rmv)),
+            (None, None) => None
         }
     }
 }
 
 impl From<&PostCopyActions> for Vec<OsString> {
     fn from(pca: &PostCopyActions) -> Self {
-        match pca {
-            PostCopyActions::AddAttribsToFiles(attribs) => vec![OsString::from(String::from("/a+:") + Into::<OsString>::into(attribs).to_str().unwrap())],
-            PostCopyActions::RmvAttribsFromFiles(attribs) => vec![OsString::from(String::from("/a-:") + Into::<OsString>::into(attribs).to_str().unwrap())],
-            PostCopyActions::_MULTIPLE(add_attribs, rmv_attribs) => vec![OsString::from(String::from("/a+:") + Into::<OsString>::into(add_attribs).to_str().unwrap()), OsString::from(String::from("/a-:") + Into::<OsString>::into(rmv_attribs).to_str().unwrap())],
+        let (add_attribs, rmv_attribs) = pca.parts();
+        let mut res = Vec::new();
+
+        if let Some(attribs) = add_attribs {
+            res.push(OsString::from(String::from("/a+:") + Into::<OsString>::into(attribs).to_str().unwrap()));
+        }
+        if let Some(attribs) = rmv_attribs {
+            res.push(OsString::from(String::from("/a-:") + Into::<OsString>::into(attribs).to_str().unwrap()));
         }
+        res
     }
 }
 impl From<PostCopyActions> for Vec<OsString> {
@@ -293,16 +416,18 @@ impl From<PostCopyActions> for Vec<OsString> {
 
 impl MultipleVariant for PostCopyActions {
     fn single_variants(&self) -> Vec<Self> {
-        match self {
-            Self::_MULTIPLE(add, rmv) => vec![Self::AddAttribsToFiles(*add), Self::RmvAttribsFromFiles(*rmv)],
-            variant => vec![*variant]
+        match self.parts() {
+            (Some(add), Some(rmv)) => vec![Self::AddAttribsToFiles(add), Self::RmvAttribsFromFiles(rmv)],
+            (Some(add), None) => vec![Self::AddAttribsToFiles(add)],
+            (None, Some(rmv)) => vec![Self::RmvAttribsFromFiles(rmv)],
+            (None, None) => Vec::new()
         }
     }
 }
 
 /// Specifies file system options
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FilesystemOptions {
     /// Creates destination files by using 8.3 character-length FAT file names only.
     /// 
@@ -338,14 +463,22 @@ impl From<FilesystemOptions> for Vec<OsString> {
 
 /// Robocopy command builder
 /// 
-#[derive(Debug, Clone)]
+/// The default builder uses `.` as both source and destination, set them before building:
+/// [try_build](RobocopyCommandBuilder::try_build) rejects a builder whose paths were left unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct RobocopyCommandBuilder<'a> {
+    /// Path to the robocopy executable.
+    /// 
+    /// `None` looks `robocopy` up in the `PATH`.
+    pub robocopy_path: Option<&'a Path>,
     /// The source's path
     pub source: &'a Path,
     /// The destination's path
     pub destination: &'a Path,
     /// Specifies the file or files to be copied. Wildcard characters are supported.
-    pub files: Vec<&'a str>,
+    /// 
+    /// The files are checked by [try_build](RobocopyCommandBuilder::try_build), see [Pattern].
+    pub files: Vec<Cow<'a, str>>,
     /// Specifies a copy strategy
     pub copy_mode: Option<CopyMode>,
     /// Copies using unbuffered I/O (recommended for large files).
@@ -353,10 +486,10 @@ pub struct RobocopyCommandBuilder<'a> {
     /// Corresponds to `/j` option.
     pub unbuffered: bool,
 
-    /// Copies subdirectories. This option automatically includes empty directories.
+    /// Specifies which subdirectories to copy, none by default like robocopy.
     /// 
-    /// Corresponds to `/e` option.
-    pub empty_dir_copy: bool,
+    /// Corresponds to `/s` or `/e` option.
+    pub subdir_mode: SubdirMode,
     /// Deletes destination files and directories that no longer exist in the source.
     /// 
     /// Corresponds to `/purge` option.
@@ -376,7 +509,11 @@ pub struct RobocopyCommandBuilder<'a> {
     pub copy_file_properties: Option<FileProperties>,
     /// Specifies what to copy in directories.
     /// 
-    /// Corresponds to `/dcopy` option.
+    /// Applies to every directory created in the destination, including the empty ones copied with [SubdirMode::INCLUDING_EMPTY].
+    /// Robocopy sets the time stamps of a directory after copying its files, so they aren't changed by the copy.
+    /// `None` uses robocopy's default of data and attributes, [DirectoryProperties::none] copies nothing.
+    /// 
+    /// Corresponds to `/dcopy` or `/nodcopy` option.
     pub copy_dir_properties: Option<DirectoryProperties>,
 
     /// Specifies the filter options.
@@ -388,6 +525,9 @@ pub struct RobocopyCommandBuilder<'a> {
     pub performance_options: Option<PerformanceOptions>,
     /// Specifies the retry options.
     pub retry_settings: Option<RetrySettings>,
+    /// Allows saving the retry settings in the registry (`/reg`), which [try_build](RobocopyCommandBuilder::try_build)
+    /// rejects otherwise since it changes the defaults of robocopy machine-wide.
+    pub allow_registry_changes: bool,
     
     /// Specifies the logging options.
     pub logging: Option<LoggingOptions<'a>>,
@@ -397,7 +537,23 @@ pub struct RobocopyCommandBuilder<'a> {
     /// Specifies what attributes to add or remove to copied files
     pub post_copy_actions: Option<PostCopyActions>,
 
-    /// To use this option empty_dir_copy and PostCopyAction::RMV_FILES_AND_DIRS_NOT_IN_SRC must also be in use
+    /// The encoding of robocopy's output, used when the output is captured.
+    /// 
+    /// `None` detects the code page of the console.
+    pub output_encoding: Option<OutputEncoding>,
+
+    /// The exit codes treated as a success, overriding robocopy's convention of codes below 8.
+    /// 
+    /// See [success_codes](RobocopyCommandBuilder::success_codes).
+    pub success_codes: Option<Vec<i8>>,
+
+    /// Credentials to connect to a remote share before running robocopy.
+    /// 
+    /// The connection is established with `net use` before each execution and deleted afterwards, even if the copy fails.
+    /// An existing connection to the share is used as is and kept.
+    pub unc_credentials: Option<UncCredentials>,
+
+    /// To use this option subdir_mode must be [SubdirMode::INCLUDING_EMPTY] and remove_files_and_dirs_not_in_src must also be in use
     pub overwrite_destination_dir_sec_settings_when_mirror: bool,
     // todo fix secfix and timfix
     // todo job options
@@ -406,12 +562,13 @@ pub struct RobocopyCommandBuilder<'a> {
 impl<'a> Default for RobocopyCommandBuilder<'a> {
     fn default() -> Self {
         RobocopyCommandBuilder {
+            robocopy_path: None,
             source: Path::new("."),
             destination: Path::new("."),
             files: Vec::new(),
             copy_mode: None,
             unbuffered: false,
-            empty_dir_copy: false,
+            subdir_mode: SubdirMode::NONE,
             remove_files_and_dirs_not_in_src: false,
             only_copy_top_n_levels: None,
             structure_and_size_zero_files_only: false,
@@ -421,24 +578,298 @@ impl<'a> Default for RobocopyCommandBuilder<'a> {
             filesystem_options: None,
             performance_options: None,
             retry_settings: None,
+            allow_registry_changes: false,
             logging: None,
             mv: None,
             post_copy_actions: None,
+            output_encoding: None,
+            success_codes: None,
+            unc_credentials: None,
             overwrite_destination_dir_sec_settings_when_mirror: false,
         }
     }
 }
 
 impl<'a> RobocopyCommandBuilder<'a> {
+    /// Returns a builder copying the single file `src_file` into the `dst_dir` directory.
+    /// 
+    /// The parent of `src_file` is used as the source and only the top level is copied,
+    /// so files with the same name in subdirectories are left alone.
+    /// 
+    /// Returns `None` if `src_file` has no file name or if its name isn't valid unicode.
+    pub fn single_file(src_file: &'a Path, dst_dir: &'a Path) -> Option<Self> {
+        let file = src_file.file_name()?.to_str()?;
+        let source = src_file.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+
+        Some(RobocopyCommandBuilder {
+            source,
+            destination: dst_dir,
+            files: vec![Cow::Borrowed(file)],
+            only_copy_top_n_levels: Some(1),
+            ..Self::default()
+        })
+    }
+
+    /// Copies only the files with one of the extensions, by adding a `*.ext` wildcard to the files to copy for each extension.
+    /// 
+    /// Extensions may be given with or without the leading dot, such as `txt` or `.txt`.
+    pub fn include_extensions(&mut self, exts: &[&'a str]) -> &mut Self {
+        self.files.extend(exts.iter().map(|ext| Cow::Owned(format!("*.{}", ext.trim_start_matches('*').trim_start_matches('.')))));
+        self
+    }
+
+    /// Returns a builder copying new and changed files from `source` to `destination`, including subdirectories.
+    /// 
+    /// Nothing is ever deleted from the destination and destination files newer than their source are left untouched.
+    /// Failed copies are retried 3 times, waiting 10 seconds between retries, rather than robocopy's million retries.
+    /// This is the recommended configuration to back up into a shared location.
+    /// 
+    /// Corresponds to `/e /xo /r:3 /w:10` options.
+    pub fn safe_one_way_sync(source: &'a Path, destination: &'a Path) -> Self {
+        RobocopyCommandBuilder {
+            source,
+            destination,
+            subdir_mode: SubdirMode::INCLUDING_EMPTY,
+            remove_files_and_dirs_not_in_src: false,
+            filter: Some(Filter {
+                file_exclusion_filter: Some(FileExclusionFilter::OLDER),
+                ..Filter::default()
+            }),
+            retry_settings: Some(RetrySettings::with(3, 10)),
+            ..Self::default()
+        }
+    }
+
+    /// Returns a builder copying large files from `source` to `destination`, including subdirectories,
+    /// so that an interrupted transfer resumes where it stopped.
+    /// 
+    /// Files are copied in restartable mode and failed copies are retried 10 times, waiting 30 seconds between retries,
+    /// with network compression requested. Unbuffered I/O is left disabled as robocopy doesn't allow it in restartable mode.
+    /// 
+    /// Corresponds to `/e /z /r:10 /w:30 /compress` options.
+    pub fn resumable_large_transfer(source: &'a Path, destination: &'a Path) -> Self {
+        RobocopyCommandBuilder {
+            source,
+            destination,
+            subdir_mode: SubdirMode::INCLUDING_EMPTY,
+            copy_mode: Some(CopyMode::RESTARTABLE_MODE),
+            unbuffered: false,
+            performance_options: Some(PerformanceOptions {
+                request_network_compression: true,
+                ..PerformanceOptions::default()
+            }),
+            retry_settings: Some(RetrySettings::with(10, 30)),
+            ..Self::default()
+        }
+    }
+
+    /// Recreates the directory tree of the source in the destination, without any file, not even zero-length ones.
+    /// 
+    /// This copies subdirectories including empty ones and excludes every file (`/e /xf *`), rather than creating
+    /// zero-length files (`/create`). Directory data, attributes and time stamps are copied (`/dcopy:DAT`)
+    /// unless directory properties are already set.
+    pub fn clone_structure_only(&mut self) -> &mut Self {
+        self.subdir_mode = SubdirMode::INCLUDING_EMPTY;
+        self.structure_and_size_zero_files_only = false;
+        self.filter.get_or_insert_with(Filter::default).add_file_exclusion_filter(FileExclusionFilter::PathOrName(vec![String::from("*")]));
+        self.copy_dir_properties.get_or_insert_with(DirectoryProperties::default_dat);
+        self
+    }
+
+    /// Returns a builder with the options of `overlay` applied on top of this one, such as per-job options on a shared template.
+    /// 
+    /// - `Option` fields of `overlay` take precedence when they are `Some`, except for file properties, directory properties
+    ///   and post-copy actions whose flags are unioned.
+    /// - The source and destination of `overlay` take precedence unless they are left to `.`, and so does its subdirectory
+    ///   mode unless it's [SubdirMode::NONE].
+    /// - The files of `overlay` are appended to the files of this builder, skipping duplicates.
+    /// - Boolean fields are set if they are set in either builder.
+    pub fn merge(&self, overlay: &Self) -> Self {
+        let mut files = self.files.clone();
+        files.extend(overlay.files.iter().filter(|file| !self.files.contains(file)).cloned());
+
+        RobocopyCommandBuilder {
+            robocopy_path: overlay.robocopy_path.or(self.robocopy_path),
+            source: if overlay.source == Path::new(".") { self.source } else { overlay.source },
+            destination: if overlay.destination == Path::new(".") { self.destination } else { overlay.destination },
+            files,
+            copy_mode: overlay.copy_mode.or(self.copy_mode),
+            unbuffered: self.unbuffered || overlay.unbuffered,
+            subdir_mode: if overlay.subdir_mode == SubdirMode::NONE { self.subdir_mode } else { overlay.subdir_mode },
+            remove_files_and_dirs_not_in_src: self.remove_files_and_dirs_not_in_src || overlay.remove_files_and_dirs_not_in_src,
+            only_copy_top_n_levels: overlay.only_copy_top_n_levels.or(self.only_copy_top_n_levels),
+            structure_and_size_zero_files_only: self.structure_and_size_zero_files_only || overlay.structure_and_size_zero_files_only,
+            copy_file_properties: union(self.copy_file_properties, overlay.copy_file_properties),
+            copy_dir_properties: union(self.copy_dir_properties, overlay.copy_dir_properties),
+            filter: overlay.filter.clone().or_else(|| self.filter.clone()),
+            filesystem_options: overlay.filesystem_options.or(self.filesystem_options),
+            performance_options: overlay.performance_options.or(self.performance_options),
+            retry_settings: overlay.retry_settings.or(self.retry_settings),
+            allow_registry_changes: self.allow_registry_changes || overlay.allow_registry_changes,
+            logging: overlay.logging.clone().or_else(|| self.logging.clone()),
+            mv: overlay.mv.or(self.mv),
+            post_copy_actions: match (self.post_copy_actions, overlay.post_copy_actions) {
+                (Some(actions), Some(overlay_actions)) => actions.merge(overlay_actions),
+                (actions, overlay_actions) => overlay_actions.or(actions)
+            },
+            output_encoding: overlay.output_encoding.or(self.output_encoding),
+            success_codes: overlay.success_codes.clone().or_else(|| self.success_codes.clone()),
+            unc_credentials: overlay.unc_credentials.clone().or_else(|| self.unc_credentials.clone()),
+            overwrite_destination_dir_sec_settings_when_mirror: self.overwrite_destination_dir_sec_settings_when_mirror || overlay.overwrite_destination_dir_sec_settings_when_mirror,
+        }
+    }
+
+    /// Returns true if the command can delete or empty files: when purging, mirroring, moving or creating zero-length files.
+    pub fn is_destructive(&self) -> bool {
+        self.remove_files_and_dirs_not_in_src || self.mv.is_some() || self.structure_and_size_zero_files_only
+    }
+
+    /// Returns true if the command mirrors the source, deleting destination files and directories that no longer exist in the source.
+    /// 
+    /// Mirroring (`/mir`) is equivalent to copying subdirectories including empty ones (`/e`) and purging (`/purge`).
+    pub fn is_mirror(&self) -> bool {
+        self.subdir_mode == SubdirMode::INCLUDING_EMPTY && self.remove_files_and_dirs_not_in_src
+    }
+
+    /// Returns true if files are deleted from the source after being copied.
+    pub fn is_move(&self) -> bool {
+        self.mv.is_some()
+    }
+
+    /// Returns true if security information (ACLs, owner or auditing information) is copied.
+    pub fn copies_security(&self) -> bool {
+        self.copy_file_properties.is_some_and(|properties| properties.single_variants().iter().any(|property| matches!(
+            property,
+            FileProperties::NTFS_ACCESS_CONTROL_LIST | FileProperties::OWNER_INFO | FileProperties::AUDITING_INFO
+        )))
+    }
+
+    /// Returns true if files are only listed, and not copied, deleted or time stamped.
+    pub fn is_list_only(&self) -> bool {
+        self.logging.as_ref().is_some_and(|logging| logging.only_log)
+    }
+
+    /// Treats only the exit codes in `codes` as a success, instead of the codes below 8.
+    /// 
+    /// Accepted failure codes are returned as [OkExitCode::ACCEPTED] and rejected success codes as [Error::RejectedExitCode].
+    pub fn success_codes(&mut self, codes: &[i8]) -> &mut Self {
+        self.success_codes = Some(codes.to_vec());
+        self
+    }
+
+    /// Returns the configuration robocopy will use, filling in its documented defaults for the options that aren't set.
+    /// 
+    /// Retry settings saved in the registry (`/reg`) aren't read, robocopy's built-in defaults are reported instead.
+    pub fn effective_config(&self) -> EffectiveConfig {
+        let retries = self.retry_settings.as_ref().and_then(|settings| settings.specify_retries_failed_copies.flatten());
+        let wait = self.retry_settings.as_ref().and_then(|settings| settings.specify_wait_between_retries.flatten());
+        let performance_choice = self.performance_options.as_ref().and_then(|options| options.performance_choice);
+
+        EffectiveConfig {
+            copy_file_properties: self.copy_file_properties.unwrap_or(FileProperties::DATA + FileProperties::ATTRIBUTES + FileProperties::TIME_STAMPS),
+            copy_dir_properties: self.copy_dir_properties.unwrap_or(DirectoryProperties::DATA + DirectoryProperties::ATTRIBUTES),
+            retries: retries.unwrap_or(1_000_000),
+            wait: wait.unwrap_or(30),
+            threads: match performance_choice {
+                Some(PerformanceChoice::Threads(threads)) => threads.map(|n| n.clamp(1, 128)).unwrap_or(8),
+                _ => 1
+            },
+            inter_packet_gap: match performance_choice {
+                Some(PerformanceChoice::InterPacketGap(gap)) => gap,
+                _ => 0
+            },
+        }
+    }
+
+    /// Checks the options for inconsistencies robocopy would silently accept.
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if self.source == Path::new(".") && self.destination == Path::new(".") {
+            return Err(BuildError::MissingPaths);
+        }
+        if self.source == self.destination {
+            return Err(BuildError::SameSourceAndDestination);
+        }
+        if self.structure_and_size_zero_files_only && self.remove_files_and_dirs_not_in_src {
+            return Err(BuildError::CreateWithPurge);
+        }
+        if self.unbuffered && matches!(self.copy_mode, Some(CopyMode::RESTARTABLE_MODE | CopyMode::RESTARTABLE_MODE_BACKUP_MODE_FALLBACK)) {
+            return Err(BuildError::UnbufferedRestartable);
+        }
+
+        for file in &self.files {
+            Pattern::new(file.as_ref())?;
+        }
+
+        if let Some(filter) = &self.filter {
+            filter.validate()?;
+            if !filter.excluded_size_bands.is_empty() {
+                return Err(BuildError::SizeBandsRequireSeveralRuns);
+            }
+        }
+        if let Some(options) = &self.performance_options {
+            options.validate()?;
+        }
+        if let Some(logging) = &self.logging {
+            logging.validate()?;
+        }
+        if !self.allow_registry_changes && self.retry_settings.is_some_and(|settings| settings.will_modify_registry()) {
+            return Err(BuildError::ModifiesRegistry);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that paths longer than `MAX_PATH` (260 characters) can be copied.
+    /// 
+    /// The source directory is walked looking for a path that would be too long, in the source or in the destination,
+    /// if the `LongPathsEnabled` registry value isn't set. Prefixing the source and destination with `\\?\`
+    /// bypasses the limit when long paths can't be enabled system-wide.
+    pub fn ensure_long_paths(&self) -> Result<(), BuildError> {
+        self.ensure_long_paths_with(long_paths_enabled)
+    }
+
+    /// Checks that long paths can be copied, reading whether they are enabled with `long_paths_enabled`
+    /// only when a long path is found
+    fn ensure_long_paths_with<F: FnOnce() -> bool>(&self, long_paths_enabled: F) -> Result<(), BuildError> {
+        let long_paths_disabled = matches!(self.filesystem_options, Some(FilesystemOptions::DISABLE_LONG_PATHS | FilesystemOptions::_MULTIPLE([_, _, true])));
+        let destination_extra_length = self.destination.as_os_str().len().saturating_sub(self.source.as_os_str().len());
+
+        match find_long_path(self.source, MAX_PATH.saturating_sub(destination_extra_length)) {
+            Some(path) if long_paths_disabled || !long_paths_enabled() => Err(BuildError::LongPathsDisabled { path }),
+            _ => Ok(())
+        }
+    }
+
+    /// Validates the options and builds the command
+    pub fn try_build(&self) -> Result<RobocopyCommand, BuildError> {
+        self.validate()?;
+        Ok(self.build())
+    }
+
+    /// Returns a description of the builder to attach to a bug report.
+    /// 
+    /// It contains the version of this crate, the configured options and the resulting arguments, in a markdown code block.
+    pub fn bug_report(&self) -> String {
+        let command = self.build();
+        let args: Vec<&std::ffi::OsStr> = command.command.get_args().collect();
+
+        format!(
+            "```\nrobocopyrs {}\n\nOptions:\n{:#?}\n\nArguments:\n{:?}\n```",
+            env!("CARGO_PKG_VERSION"), self, args
+        )
+    }
+
     /// Build the command
     pub fn build(&self) -> RobocopyCommand {
-        let mut command = Command::new("robocopy");
+        let mut command = Command::new(self.robocopy_path.unwrap_or_else(|| Path::new("robocopy")));
         
         command
             .arg(self.source)
             .arg(self.destination);
 
-        self.files.iter().for_each(|file| {command.arg(file);});
+        self.files.iter().for_each(|file| {command.arg(file.as_ref() as &str);});
 
         if let Some(mode) = &self.copy_mode {
             command.arg(Into::<OsString>::into(mode));
@@ -447,17 +878,13 @@ impl<'a> RobocopyCommandBuilder<'a> {
             command.arg("/j");
         }
         
-        if self.empty_dir_copy && 
+        if self.subdir_mode == SubdirMode::INCLUDING_EMPTY && 
                 self.remove_files_and_dirs_not_in_src && 
                 self.overwrite_destination_dir_sec_settings_when_mirror {
             command.arg("/mir");
             command.arg("/e");
         } else {
-            if self.empty_dir_copy {
-                command.arg("/e");
-            } else {
-                command.arg("/s");
-            }
+            Into::<Vec<OsString>>::into(self.subdir_mode).into_iter().for_each(|arg| {command.arg(arg);});
             
             if self.remove_files_and_dirs_not_in_src {
                 command.arg("/purge");
@@ -504,19 +931,152 @@ impl<'a> RobocopyCommandBuilder<'a> {
             Into::<Vec<OsString>>::into(actions).into_iter().for_each(|arg| {command.arg(arg);});
         }
 
-        RobocopyCommand { command }        
+        RobocopyCommand {
+            command,
+            source: self.source.to_path_buf(),
+            destination: self.destination.to_path_buf(),
+            encoding: self.output_encoding.unwrap_or_else(OutputEncoding::detect),
+            success_codes: self.success_codes.clone(),
+            unc_credentials: self.unc_credentials.clone(),
+            log_rotation: self.logging.as_ref()
+                .and_then(|logging| logging.log_file)
+                .and_then(|settings| settings.max_bytes.map(|max_bytes| (settings.log.to_path_buf(), max_bytes))),
+        }
     }
 }
 
+/// The configuration robocopy uses once its defaults are applied, see [RobocopyCommandBuilder::effective_config]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveConfig {
+    /// The file properties copied, data, attributes and time stamps by default (`/copy:DAT`)
+    pub copy_file_properties: FileProperties,
+    /// The directory properties copied, data and attributes by default (`/dcopy:DA`)
+    pub copy_dir_properties: DirectoryProperties,
+    /// The number of retries on failed copies, one million by default (`/r:1000000`)
+    pub retries: usize,
+    /// The wait time between retries in seconds, 30 by default (`/w:30`)
+    pub wait: usize,
+    /// The number of copy threads, 1 without multithreading and 8 by default with it (`/mt:8`)
+    pub threads: u8,
+    /// The inter-packet gap in milliseconds, 0 by default
+    pub inter_packet_gap: usize,
+}
+
+/// An inconsistency found while validating a [RobocopyCommandBuilder]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// The source and destination were left to their default value
+    #[error("Source and destination must be set")]
+    MissingPaths,
+    /// The source and destination are the same directory
+    #[error("Source and destination are the same")]
+    SameSourceAndDestination,
+    /// A path is longer than `MAX_PATH` but long paths support is disabled
+    #[error("Path {path:?} is too long while long paths are disabled, enable LongPathsEnabled or prefix the paths with \\\\?\\")]
+    LongPathsDisabled {
+        /// The first path found to be too long
+        path: PathBuf,
+    },
+    /// Creating zero-length files (`/create`) while purging (`/purge` or `/mir`) replaces the destination files
+    /// with empty placeholders.
+    /// 
+    /// This is only legitimate when the destination is meant to become a skeleton of the source,
+    /// use [build](RobocopyCommandBuilder::build) to skip the validation in that case.
+    #[error("Creating zero-length files while purging replaces destination files with empty ones")]
+    CreateWithPurge,
+    /// A file to copy isn't a valid file name or wildcard pattern
+    #[error("Invalid file pattern: {0}")]
+    Pattern(#[from] PatternError),
+    /// The retry settings are saved in the registry (`/reg`), changing the defaults of robocopy machine-wide
+    /// 
+    /// Set [allow_registry_changes](RobocopyCommandBuilder::allow_registry_changes) when this is intended.
+    #[error("Saving the retry settings changes the defaults of robocopy in the registry")]
+    ModifiesRegistry,
+    /// Unbuffered I/O (`/j`) is used in restartable mode (`/z` or `/zb`), which robocopy doesn't allow
+    #[error("Unbuffered I/O can't be used in restartable mode")]
+    UnbufferedRestartable,
+    /// The filter options are inconsistent
+    #[error("Invalid filter: {0}")]
+    Filter(#[from] FilterError),
+    /// The filter excludes size bands, which robocopy can't do in a single run
+    /// 
+    /// See [Filter::size_band_filters] to get the filter of each run.
+    #[error("Excluding size bands requires several runs, see Filter::size_band_filters")]
+    SizeBandsRequireSeveralRuns,
+    /// The performance options are inconsistent
+    #[error("Invalid performance options: {0}")]
+    Performance(#[from] PerformanceError),
+    /// The logging options contradict each other
+    #[error("Invalid logging options: {0}")]
+    Logging(#[from] LoggingError),
+}
+
 /// A enum on error that can occurs during command execution
 #[derive(Error, Debug)]
 pub enum Error {
     /// An error occured during copy
     #[error("Error during copy: {0:?}")]
     ExitCode(ErrExitCode),
+    /// An error occured during copy, with the job summary of what was done before the failure
+    #[error("Error during copy: {code:?}")]
+    ExitCodeWithReport {
+        /// The exit code of robocopy
+        code: ErrExitCode,
+        /// The job summary printed by robocopy
+        report: Box<RobocopyReport>,
+    },
+    /// A success exit code that isn't one of the success codes of the builder
+    #[error("Exit code {0:?} isn't one of the success codes")]
+    RejectedExitCode(OkExitCode),
     /// IO error during command spawning
     #[error("IO error")]
-    IoError(#[from] io::Error)
+    IoError(#[from] io::Error),
+    /// The output of robocopy could not be parsed
+    #[error("Unable to parse robocopy report: {context}")]
+    ReportParse {
+        /// What went wrong, including the offending line when there is one
+        context: String,
+        /// The underlying error
+        #[source]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+    /// The dry run of a guarded mirror would delete more files than allowed
+    #[error("Mirroring would delete {deletions} files or directories, more than the {max_deletions} allowed")]
+    TooManyDeletions {
+        /// The number of files and directories the mirror would delete
+        deletions: usize,
+        /// The maximum number of deletions that was allowed
+        max_deletions: usize,
+    },
+    /// The command moves files (`/mov` or `/move`), so their sources can't be hashed after the copy,
+    /// see `RobocopyCommand::execute_verified` (`verify` feature)
+    #[error("Moved files can't be verified since their sources are deleted")]
+    VerifyMovedFiles,
+    /// The destructive operations were declined, see [RobocopyCommand::execute_with_confirm]
+    #[error("Deleting or moving files wasn't confirmed")]
+    NotConfirmed,
+    /// The connection to a remote share failed
+    #[error("Unable to connect to {share:?}: {message}")]
+    UncConnection {
+        /// The share robocopy had to connect to
+        share: PathBuf,
+        /// The error printed by `net use`
+        message: String,
+    },
+    /// The connection to a remote share failed because a connection to the same server exists with other credentials (error 1219).
+    /// 
+    /// Windows allows a single set of credentials per server, the existing connections have to be deleted first.
+    #[error("Unable to connect to {share:?}: the server is already connected with other credentials (error 1219)")]
+    UncConflictingCredentials {
+        /// The share robocopy had to connect to
+        share: PathBuf,
+    },
+    /// Robocopy was denied access to a file or directory (error 5)
+    #[error("Access denied to {path:?}, try using the backup mode or running with elevated privileges")]
+    AccessDenied {
+        /// The file or directory robocopy failed to access
+        path: PathBuf,
+    },
 }
 
 impl From<ErrExitCode> for Error {
@@ -527,30 +1087,1555 @@ impl From<ErrExitCode> for Error {
 
 /// A wrapper around a [Command]
 pub struct RobocopyCommand {
-    command: Command
+    command: Command,
+    source: PathBuf,
+    destination: PathBuf,
+    encoding: OutputEncoding,
+    success_codes: Option<Vec<i8>>,
+    unc_credentials: Option<UncCredentials>,
+    log_rotation: Option<(PathBuf, u64)>,
 }
 
 impl RobocopyCommand {
+    /// The source's path
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// The destination's path
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Returns the command line, quoting arguments as needed so that it can be pasted into a terminal.
+    /// 
+    /// Arguments are quoted following the Windows command-line parsing rules.
+    pub fn to_command_line(&self) -> String {
+        std::iter::once(self.command.get_program())
+            .chain(self.command.get_args())
+            .map(|arg| quote_arg(&arg.to_string_lossy()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Returns a robocopy command running `command` with the same paths and encoding
+    fn with_command(&self, command: Command) -> RobocopyCommand {
+        RobocopyCommand {
+            command,
+            source: self.source.clone(),
+            destination: self.destination.clone(),
+            encoding: self.encoding,
+            success_codes: self.success_codes.clone(),
+            unc_credentials: self.unc_credentials.clone(),
+            log_rotation: self.log_rotation.clone(),
+        }
+    }
+
+    /// Returns a new [Command] with the same program, arguments, environment and working directory, without consuming this command.
+    /// 
+    /// This allows customizing the [Command], such as its standard streams, while keeping the robocopy command.
+    /// Arguments added by previous executions, such as `/fp`, are included.
+    pub fn to_command(&self) -> Command {
+        self.to_command_without(|_| false)
+    }
+
+    /// Returns a new [Command] like [to_command](RobocopyCommand::to_command), without the arguments matching `remove`
+    fn to_command_without<F: Fn(&OsStr) -> bool>(&self, remove: F) -> Command {
+        let mut command = Command::new(self.command.get_program());
+        command.args(self.command.get_args().filter(|arg| !remove(arg)));
+        if let Some(dir) = self.command.get_current_dir() {
+            command.current_dir(dir);
+        }
+        for (key, value) in self.command.get_envs() {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key)
+            };
+        }
+        command
+    }
+
     /// Executes the command as a child process, waiting for it to finish and returning its status
+    /// 
+    /// With the `tracing` feature, the execution is recorded in a `robocopy` span.
     pub fn execute(&mut self) -> Result<OkExitCode, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        self.rotate_log()?;
+        let _connection = self.connect()?;
         let exit_code = self.command.status()?
-        .code().expect("Process terminated by signal") as i8;
+        .code().expect("Process terminated by signal");
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(exit_code, "robocopy finished");
     
-        OkExitCode::try_from(exit_code).map_err(|err| err.into())
+        self.exit_code(exit_code)
     }
-}
 
-#[allow(clippy::from_over_into)]
-impl Into<Command> for RobocopyCommand {
-    /// Converts this robocopy command into a [Command].
-    /// Effectively returning the underlying [Command]
-    fn into(self) -> Command {
-        self.command
+    /// Executes the command as a child process, capturing its standard output.
+    /// 
+    /// A failure caused by robocopy being denied access to a file or directory is reported as [Error::AccessDenied].
+    /// Other failures are reported as [Error::ExitCodeWithReport] when robocopy printed a job summary.
+    /// Full path names (`/fp`) are enabled so that the reported path can be resolved.
+    pub fn output(&mut self) -> Result<(OkExitCode, String), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        self.capture()
     }
-}
 
-impl Debug for RobocopyCommand {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", format!("{:?}", self.command).replace('\"', ""))
+    /// Executes the command as a child process, returning the copied files and bytes from the job summary.
+    /// 
+    /// The duration is read from the job summary, or measured around the process if robocopy didn't print it.
+    /// Whether backup mode was used is read from the output, to tell if `/zb` needed the backup privileges.
+    /// Like [output](RobocopyCommand::output), full path names (`/fp`) are enabled.
+    /// 
+    /// With the `tracing` feature, the statistics are recorded in the `robocopy` span.
+    pub fn execute_captured(&mut self) -> Result<CopyResult, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        let start = Instant::now();
+        let (code, stdout) = self.capture()?;
+        let elapsed = start.elapsed();
+        let report = RobocopyReport::parse(&stdout)?;
+
+        #[cfg(feature = "tracing")]
+        tracing::info!(files_copied = report.files.copied, bytes_copied = report.bytes.copied, "robocopy report");
+
+        Ok(CopyResult {
+            code,
+            bytes_copied: report.bytes.copied,
+            files_copied: report.files.copied,
+            duration: report.times.map(|times| times.total).unwrap_or(elapsed),
+            report,
+            used_backup_mode: self.has_arg("/b") || (self.has_arg("/zb") && report::used_backup_fallback(&stdout)),
+        })
+    }
+
+    fn capture(&mut self) -> Result<(OkExitCode, String), Error> {
+        self.require_full_paths();
+        self.rotate_log()?;
+        let _connection = self.connect()?;
+
+        let output = self.command.output()?;
+        let stdout = self.encoding.decode(&output.stdout);
+        let exit_code = output.status.code().expect("Process terminated by signal");
+
+        self.exit_result(exit_code, &stdout).map(|exit_code| (exit_code, stdout))
+    }
+
+    /// Executes the command as a child process, calling `on_event` for each line and progress update
+    /// while it runs, and returning the job summary once it finished.
+    /// 
+    /// Like [output](RobocopyCommand::output), full path names (`/fp`) are enabled.
+    /// 
+    /// With the `tracing` feature, each line of output is also recorded as a debug event.
+    pub fn execute_streaming_report<F: FnMut(ProgressEvent)>(&mut self, mut on_event: F) -> Result<(OkExitCode, RobocopyReport), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        self.require_full_paths();
+        self.rotate_log()?;
+        let _connection = self.connect()?;
+
+        let mut process = self.to_command().stdout(Stdio::piped()).spawn()?;
+        let mut reader = BufReader::new(process.stdout.take().expect("stdout is piped"));
+        let mut stdout = String::new();
+        let mut line = Vec::new();
+        let encoding = self.encoding;
+
+        let mut handle_line = |line: &[u8]| {
+            let line = encoding.decode(line);
+            if line.trim().is_empty() {
+                return;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!("{}", line);
+
+            on_event(ProgressEvent::classify(&line));
+            stdout.push_str(&line);
+            stdout.push('\n');
+        };
+
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            for byte in buffer {
+                match byte {
+                    b'\r' | b'\n' => {
+                        handle_line(&line);
+                        line.clear();
+                    },
+                    byte => line.push(*byte)
+                }
+            }
+            let length = buffer.len();
+            reader.consume(length);
+        }
+        handle_line(&line);
+
+        let exit_code = process.wait()?.code().expect("Process terminated by signal");
+        let exit_code = self.exit_result(exit_code, &stdout)?;
+
+        Ok((exit_code, RobocopyReport::parse(&stdout)?))
+    }
+
+    fn exit_result(&self, exit_code: i32, stdout: &str) -> Result<OkExitCode, Error> {
+        #[cfg(feature = "tracing")]
+        tracing::info!(exit_code, "robocopy finished");
+
+        self.exit_code(exit_code).map_err(|err| match (err, report::access_denied_path(stdout), RobocopyReport::parse(stdout)) {
+            (Error::ExitCode(_), Some(path), _) => Error::AccessDenied { path },
+            (Error::ExitCode(code), None, Ok(report)) => Error::ExitCodeWithReport { code, report: Box::new(report) },
+            (err, _, _) => err
+        })
+    }
+
+    /// Maps the exit code to a success or a failure, following the success codes if they are set
+    fn exit_code(&self, exit_code: i32) -> Result<OkExitCode, Error> {
+        let accepted = self.success_codes.as_ref()
+            .map(|codes| i8::try_from(exit_code).is_ok_and(|exit_code| codes.contains(&exit_code)));
+
+        match (OkExitCode::try_from(exit_code), accepted) {
+            (Ok(code), Some(false)) => Err(Error::RejectedExitCode(code)),
+            (Err(_), Some(true)) => Ok(OkExitCode::ACCEPTED(exit_code)),
+            (Ok(code), _) => Ok(code),
+            (Err(code), _) => Err(code.into())
+        }
+    }
+
+    /// Executes the command as a child process, returning each file listed by robocopy with its classification.
+    /// 
+    /// The command itself isn't modified: a copy is executed with verbose output (`/v`), full path names (`/fp`),
+    /// sizes in bytes (`/bytes`) and without progress (`/np`) so that the records can be parsed. The options hiding files
+    /// or the job summary, or changing the encoding of the output (`/nc /ns /nfl /ndl /njs /unicode`), are removed from the copy,
+    /// and the output is also written to the console (`/tee`) when it's logged to a file.
+    /// 
+    /// [Error::ReportParse] is returned if the output has no job summary, or if the summary counts copied or extra files
+    /// but no record could be parsed.
+    pub fn execute_records(&mut self) -> Result<(OkExitCode, Vec<FileRecord>), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        let mut command = self.parseable_copy();
+        command.require_args(&["/v", "/bytes", "/np"]);
+        let (exit_code, stdout) = command.capture()?;
+
+        Ok((exit_code, command.parse_records(&stdout)?))
     }
-}
\ No newline at end of file
+
+    /// Returns a copy of the command whose standard output lists every file and ends with the job summary.
+    /// 
+    /// The options hiding them or changing the encoding of the output (`/nc /ns /nfl /ndl /njs /unicode`) are removed,
+    /// and the output is also written to the console (`/tee`) when it's written to a log file.
+    fn parseable_copy(&self) -> RobocopyCommand {
+        let mut copy = self.with_command(self.to_command_without(is_hiding_arg));
+        if self.command.get_args().any(is_log_arg) {
+            copy.require_args(&["/tee"]);
+        }
+        copy
+    }
+
+    /// Parses the file records of the output, checking that none is missing from the job summary
+    fn parse_records(&self, stdout: &str) -> Result<Vec<FileRecord>, Error> {
+        let report = RobocopyReport::parse(stdout)?;
+        let records = FileRecord::parse_all(stdout);
+
+        let mut listed = report.files.copied + report.files.extras;
+        if !self.has_arg("/ndl") {
+            listed += report.dirs.copied + report.dirs.extras;
+        }
+        if records.is_empty() && listed > 0 {
+            return Err(Error::ReportParse {
+                context: format!("the job summary counts {} copied or extra items but no file record was found", listed),
+                source: None,
+            });
+        }
+
+        Ok(records)
+    }
+
+    /// Returns true if the command has the argument
+    fn has_arg(&self, arg: &str) -> bool {
+        self.command.get_args().any(|existing| existing.eq_ignore_ascii_case(arg))
+    }
+
+    /// Mirrors the source to the destination (`/mir`), after checking with a dry run (`/l`) that it doesn't
+    /// delete more than `max_deletions` files and directories from the destination.
+    /// 
+    /// This is a safety net against pointing a mirror at the wrong destination. Nothing is copied nor deleted
+    /// when the dry run exceeds the threshold, [Error::TooManyDeletions] is returned instead. The mirror isn't executed
+    /// either if the output of the dry run can't be parsed.
+    pub fn execute_mirror_guarded(&mut self, max_deletions: usize) -> Result<OkExitCode, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        self.require_args(&["/mir"]);
+
+        let deletions = count_deletions(&self.dry_run_records()?);
+        if deletions > max_deletions {
+            return Err(Error::TooManyDeletions { deletions, max_deletions });
+        }
+
+        self.execute()
+    }
+
+    /// Returns a copy of the command listing what it would do (`/l`) on the standard output, without writing any log file.
+    /// 
+    /// The success codes don't apply to dry runs, whose exit codes are mapped like [execute](RobocopyCommand::execute) does by default.
+    fn dry_run_command(&self) -> RobocopyCommand {
+        let mut dry_run = self.with_command(self.to_command_without(|arg| {
+            is_hiding_arg(arg) || is_log_arg(arg) || arg.eq_ignore_ascii_case("/tee")
+        }));
+        dry_run.success_codes = None;
+        dry_run.log_rotation = None;
+        dry_run.require_args(&["/l"]);
+        dry_run
+    }
+
+    /// Executes the command after `confirm` accepted the summary of what it would delete or move.
+    /// 
+    /// When the command purges (`/purge` or `/mir`) or moves (`/mov` or `/move`), a dry run (`/l`) is executed first
+    /// to count the items that would be deleted or moved. Nothing is copied if `confirm` returns false,
+    /// [Error::NotConfirmed] is returned instead, nor if the output of the dry run can't be parsed.
+    /// Other commands are executed without confirmation.
+    pub fn execute_with_confirm<F: FnMut(&DestructiveSummary) -> bool>(&mut self, mut confirm: F) -> Result<OkExitCode, Error> {
+        let purges = self.has_arg("/purge") || self.has_arg("/mir");
+        let moves = self.has_arg("/mov") || self.has_arg("/move");
+        if !purges && !moves {
+            return self.execute();
+        }
+
+        let records = self.dry_run_records()?;
+        let summary = DestructiveSummary {
+            deletions: if purges { count_deletions(&records) } else { 0 },
+            moves: if moves { records.iter().filter(|record| self.is_copied(record.class)).count() } else { 0 },
+        };
+
+        if !confirm(&summary) {
+            return Err(Error::NotConfirmed);
+        }
+
+        self.execute()
+    }
+
+    /// Executes a dry run (`/l`) of the command, returning the file records of its verbose output.
+    /// 
+    /// Like [execute_records](RobocopyCommand::execute_records), the options hiding files or the job summary are removed,
+    /// and so is logging (`/log /unilog /tee`) so that the log file only describes real runs. [Error::ReportParse] is
+    /// returned if the job summary is missing or if it counts files that aren't listed, so that callers relying on the
+    /// records to decide whether to run the command fail closed.
+    fn dry_run_records(&self) -> Result<Vec<FileRecord>, Error> {
+        let mut dry_run = self.dry_run_command();
+        dry_run.require_args(&["/v", "/bytes", "/np"]);
+        let (_, stdout) = dry_run.capture()?;
+
+        dry_run.parse_records(&stdout)
+    }
+
+    /// Copies only the files modified since the last successful run, whose date is stored in the `marker` file.
+    /// 
+    /// The marker contains the UTC date (YYYYMMDD) of the day before the last successful run started, and is used as the maximum age.
+    /// Robocopy compares dates in the local time zone and with a day precision, taking the day before ensures no file modified
+    /// since the last run is missed, at the cost of copying again the files modified in the two days before it.
+    /// If the marker doesn't exist, every file is copied. On success, the marker is (re)written for this run.
+    /// 
+    /// The command shouldn't set a maximum age (`/maxage`) itself.
+    pub fn execute_incremental(&mut self, marker: &Path) -> Result<OkExitCode, Error> {
+        let started = SystemTime::now();
+
+        let mut command = self.to_command();
+        match std::fs::read_to_string(marker) {
+            Ok(date) => {
+                let date = date.trim();
+                if date.len() != 8 || !date.bytes().all(|byte| byte.is_ascii_digit()) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid date in marker {:?}: {}", marker, date)).into());
+                }
+                command.arg(format!("/maxage:{}", date));
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+            Err(err) => return Err(err.into())
+        }
+
+        let exit_code = self.with_command(command).execute()?;
+        std::fs::write(marker, filter::robocopy_date_since(started))?;
+
+        Ok(exit_code)
+    }
+
+    /// Connects to the remote share if credentials are set, the connection is deleted when dropped
+    fn connect(&self) -> Result<Option<UncConnection>, Error> {
+        self.unc_credentials.as_ref().map(UncConnection::connect).transpose()
+    }
+
+    /// Rotates the log file if it's larger than its maximum size
+    fn rotate_log(&self) -> io::Result<()> {
+        match &self.log_rotation {
+            Some((log, max_bytes)) => logging::rotate_log(log, *max_bytes),
+            None => Ok(())
+        }
+    }
+
+    /// Lists the changes the command would make, without copying nor deleting anything.
+    /// 
+    /// A dry run (`/l`) is executed with verbose output, full path names, sizes in bytes and without directory names
+    /// (`/v /fp /bytes /ndl /np`), files excluded by the filters aren't listed as changes. The options hiding files and the logging
+    /// options are removed from the dry run, [Error::ReportParse] is returned if its output can't be parsed.
+    pub fn diff(&mut self) -> Result<SyncDiff, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        let mut dry_run = self.dry_run_command();
+        dry_run.require_args(&["/v", "/bytes", "/ndl", "/np"]);
+        let (_, stdout) = dry_run.capture()?;
+
+        let mut diff = SyncDiff::default();
+        for record in dry_run.parse_records(&stdout)? {
+            match record.class {
+                FileClass::EXTRA_FILE => diff.extra.push(record.path),
+                class if self.is_copied(class) => {
+                    diff.total_bytes += record.size.unwrap_or(0);
+                    if class == FileClass::NEW_FILE {
+                        diff.new.push(record.path);
+                    } else {
+                        diff.modified.push(record.path);
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Returns the number of files and bytes the command would copy, without copying anything.
+    /// 
+    /// A dry run (`/l`) is executed without job header, file and directory names (`/njh /nfl /ndl /np`),
+    /// only the job summary is parsed, with sizes in bytes (`/bytes`). The logging options are removed from the dry run.
+    pub fn estimate(&mut self) -> Result<Estimate, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = self.enter_span();
+
+        let mut dry_run = self.dry_run_command();
+        dry_run.require_args(&["/bytes", "/njh", "/nfl", "/ndl", "/np"]);
+        let (_, stdout) = dry_run.capture()?;
+        let report = RobocopyReport::parse(&stdout)?;
+
+        Ok(Estimate {
+            total_files: report.files.copied,
+            total_bytes: report.bytes.copied,
+        })
+    }
+
+    /// Executes the command, then compares the hashes of each copied file in the source and in the destination.
+    /// 
+    /// Copied files are read from verbose output, like [execute_records](RobocopyCommand::execute_records),
+    /// so files excluded by the filters aren't verified. Files are hashed by chunks, large files aren't loaded in memory.
+    /// 
+    /// Moved files (`/mov` or `/move`) can't be verified, [Error::VerifyMovedFiles] is returned without copying anything.
+    /// 
+    /// This requires the `verify` feature.
+    #[cfg(feature = "verify")]
+    pub fn execute_verified(&mut self, algo: verify::HashAlgo) -> Result<verify::VerifyReport, Error> {
+        if self.has_arg("/mov") || self.has_arg("/move") {
+            return Err(Error::VerifyMovedFiles);
+        }
+
+        let (code, records) = self.execute_records()?;
+        let source = std::path::absolute(&self.source)?;
+
+        let mut report = verify::VerifyReport { code, verified: 0, mismatches: Vec::new() };
+        for record in records.iter().filter(|record| self.is_copied(record.class)) {
+            let relative = match record.path.strip_prefix(&source).or_else(|_| record.path.strip_prefix(&self.source)) {
+                Ok(relative) => relative,
+                Err(_) => continue
+            };
+            let destination = self.destination.join(relative);
+
+            if verify::same_content(algo, &record.path, &destination)? {
+                report.verified += 1;
+            } else {
+                report.mismatches.push(destination);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Returns true if files of this class are copied, rather than skipped, by the command
+    fn is_copied(&self, class: FileClass) -> bool {
+        match class {
+            FileClass::NEW_FILE => true,
+            FileClass::OLDER => !self.has_arg("/xo"),
+            FileClass::NEWER => !self.has_arg("/xn"),
+            FileClass::CHANGED => !self.has_arg("/xc"),
+            FileClass::SAME => self.has_arg("/is"),
+            FileClass::TWEAKED => self.has_arg("/it"),
+            FileClass::MODIFIED => self.has_arg("/im"),
+            _ => false
+        }
+    }
+
+    /// Adds the arguments missing from the command
+    fn require_args(&mut self, args: &[&str]) {
+        for arg in args {
+            if !self.has_arg(arg) {
+                self.command.arg(arg);
+            }
+        }
+    }
+
+    /// Adds `/fp` to the arguments if missing, paths found in the output can't be resolved without it.
+    /// 
+    /// With the `tracing` feature, a warning is emitted when `/fp` has to be added.
+    fn require_full_paths(&mut self) {
+        if !self.has_arg("/fp") {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("full path names are disabled, enabling /fp to extract paths from the output");
+
+            self.command.arg("/fp");
+        }
+    }
+
+    /// Executes the command and, on success, opens the destination folder in Explorer.
+    /// 
+    /// This is a convenience for Windows desktop applications only. Failing to launch `explorer.exe`
+    /// is ignored and doesn't change the returned exit code.
+    pub fn execute_and_reveal(&mut self) -> Result<OkExitCode, Error> {
+        self.execute_and_reveal_with(|destination| {
+            let _ = Command::new("explorer.exe").arg(destination).spawn();
+        })
+    }
+
+    /// Executes the command and calls `reveal` with the destination on success
+    fn execute_and_reveal_with<F: FnOnce(&Path)>(&mut self, reveal: F) -> Result<OkExitCode, Error> {
+        let exit_code = self.execute()?;
+        reveal(&self.destination);
+        Ok(exit_code)
+    }
+
+    /// Enters the `robocopy` span, recording the source and destination of the command
+    #[cfg(feature = "tracing")]
+    fn enter_span(&self) -> tracing::span::EnteredSpan {
+        tracing::info_span!("robocopy", source = ?self.source, destination = ?self.destination).entered()
+    }
+}
+
+/// The arguments hiding file records or the job summary from the output, or changing its encoding
+const HIDING_ARGS: [&str; 6] = ["/nc", "/ns", "/nfl", "/ndl", "/njs", "/unicode"];
+
+/// Counts the destination files and directories a purge would delete, which are listed as extra
+fn count_deletions(records: &[FileRecord]) -> usize {
+    records.iter().filter(|record| matches!(record.class, FileClass::EXTRA_FILE | FileClass::EXTRA_DIR)).count()
+}
+
+/// Returns true if the argument hides files or the job summary from the output, or changes its encoding
+fn is_hiding_arg(arg: &OsStr) -> bool {
+    HIDING_ARGS.iter().any(|hiding| arg.eq_ignore_ascii_case(hiding))
+}
+
+/// Returns true if the argument writes the output to a log file (`/log`, `/log+`, `/unilog` or `/unilog+`)
+fn is_log_arg(arg: &OsStr) -> bool {
+    let arg = arg.to_string_lossy().to_lowercase();
+    ["/log:", "/log+:", "/unilog:", "/unilog+:"].iter().any(|prefix| arg.starts_with(prefix))
+}
+
+/// Returns the union of the flags if both are set, or the one that is set
+fn union<T: Add<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b)
+    }
+}
+
+/// The maximum length of a path when long paths are disabled
+const MAX_PATH: usize = 260;
+
+/// Reads the `LongPathsEnabled` registry value, returns false if it can't be read
+fn long_paths_enabled() -> bool {
+    Command::new("reg")
+        .args(["query", r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem", "/v", "LongPathsEnabled"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).lines().any(|line| line.contains("LongPathsEnabled") && line.trim_end().ends_with("0x1")))
+        .unwrap_or(false)
+}
+
+/// Returns the first path under `dir` whose length reaches `max_length`
+fn find_long_path(dir: &Path, max_length: usize) -> Option<PathBuf> {
+    std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| {
+            let path = entry.path();
+            if path.as_os_str().len() >= max_length {
+                Some(path)
+            } else if entry.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+                find_long_path(&path, max_length)
+            } else {
+                None
+            }
+        })
+}
+
+/// Quotes an argument if it contains whitespaces, quotes or shell special characters
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '"', '&', '|', '<', '>', '^', '(', ')']) {
+        return arg.to_owned();
+    }
+
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            },
+            // Backslashes are only escaped when followed by a quote
+            '"' => quoted.push_str(&"\\".repeat(backslashes * 2 + 1)),
+            _ => quoted.push_str(&"\\".repeat(backslashes))
+        }
+        quoted.push(c);
+        backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+
+    quoted
+}
+
+#[allow(clippy::from_over_into)]
+impl Into<Command> for RobocopyCommand {
+    /// Converts this robocopy command into a [Command].
+    /// Effectively returning the underlying [Command]
+    fn into(self) -> Command {
+        self.command
+    }
+}
+
+impl From<&RobocopyCommand> for Command {
+    /// Returns a new [Command], see [RobocopyCommand::to_command]
+    fn from(command: &RobocopyCommand) -> Self {
+        command.to_command()
+    }
+}
+
+impl Debug for RobocopyCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", format!("{:?}", self.command).replace('\"', ""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use performance::PerformanceChoice;
+    use report::FileClass;
+    use test_support::{FakeRobocopy, TempDir};
+
+    /// Returns a command running the fake robocopy, copying from `source` to `destination`
+    fn fake_command(fake: &FakeRobocopy) -> RobocopyCommand {
+        let mut command = Command::new(fake.path());
+        command.arg("source").arg("destination");
+        RobocopyCommand { command, source: PathBuf::from("source"), destination: PathBuf::from("destination"), encoding: OutputEncoding::Utf8, success_codes: None, unc_credentials: None, log_rotation: None }
+    }
+
+    /// Returns a builder copying between two directories of `dir` with the fake robocopy
+    fn fake_builder<'a>(fake: &'a FakeRobocopy, dir: &'a TempDir) -> RobocopyCommandBuilder<'a> {
+        RobocopyCommandBuilder {
+            robocopy_path: Some(fake.path()),
+            source: dir.path(),
+            destination: Path::new("destination"),
+            output_encoding: Some(OutputEncoding::Utf8),
+            ..RobocopyCommandBuilder::default()
+        }
+    }
+
+    #[test]
+    fn reveals_destination_only_on_success() {
+        let fake = FakeRobocopy::new("", 1);
+        let mut revealed = None;
+        let result = fake_command(&fake).execute_and_reveal_with(|destination| revealed = Some(destination.to_path_buf()));
+        assert!(matches!(result, Ok(OkExitCode::SOME_COPIES)));
+        assert_eq!(revealed, Some(PathBuf::from("destination")));
+        assert_eq!(fake.calls().len(), 1);
+
+        let fake = FakeRobocopy::new("", 8);
+        let mut revealed = false;
+        let result = fake_command(&fake).execute_and_reveal_with(|_| revealed = true);
+        assert!(matches!(result, Err(Error::ExitCode(ErrExitCode::FAIL))));
+        assert!(!revealed);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn execution_span_records_paths_and_exit_code() {
+        let fake = FakeRobocopy::new("", 3);
+
+        assert!(matches!(fake_command(&fake).execute(), Ok(OkExitCode::SOME_COPIES_EXTRA_FOUND)));
+
+        assert!(logs_contain("robocopy{source=\"source\" destination=\"destination\"}"));
+        assert!(logs_contain("robocopy finished exit_code=3"));
+    }
+
+    fn args(command: &RobocopyCommand) -> Vec<String> {
+        command.command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn single_file_copies_only_the_file() {
+        let builder = RobocopyCommandBuilder::single_file(Path::new("data/report.pdf"), Path::new("backup")).unwrap();
+
+        assert_eq!(builder.source, Path::new("data"));
+        assert_eq!(builder.destination, Path::new("backup"));
+        assert_eq!(builder.files, ["report.pdf"]);
+        assert_eq!(args(&builder.build()), ["data", "backup", "report.pdf", "/lev:1"]);
+
+        let relative = RobocopyCommandBuilder::single_file(Path::new("report.pdf"), Path::new("backup")).unwrap();
+        assert_eq!(relative.source, Path::new("."));
+        assert!(RobocopyCommandBuilder::single_file(Path::new(".."), Path::new("backup")).is_none());
+    }
+
+    #[test]
+    fn access_denied_failure_reports_path() {
+        let fake = FakeRobocopy::new("2024/03/15 10:00:00 ERROR 5 (0x00000005) Accessing Source Directory C:\\source\\private\\\nAccess is denied.\n", 8);
+
+        match fake_command(&fake).output() {
+            Err(Error::AccessDenied { path }) => assert_eq!(path, PathBuf::from("C:\\source\\private\\")),
+            result => panic!("expected an access denied error, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn try_build_validates_performance_options() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            performance_options: Some(PerformanceOptions { performance_choice: Some(PerformanceChoice::Threads(Some(200))), ..PerformanceOptions::default() }),
+            ..RobocopyCommandBuilder::default()
+        };
+
+        assert_eq!(builder.try_build().unwrap_err(), BuildError::Performance(PerformanceError::InvalidThreadCount(200)));
+    }
+
+    #[test]
+    fn try_build_validates_logging_options() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            logging: Some(LoggingOptions { dont_log_file_names: true, verbose: true, ..LoggingOptions::default() }),
+            ..RobocopyCommandBuilder::default()
+        };
+        assert_eq!(builder.try_build().unwrap_err(), BuildError::Logging(LoggingError::FileDetailsWithoutFileNames("/v")));
+    }
+
+    const SUMMARY: &str = "\
+------------------------------------------------------------------------------
+
+               Total    Copied   Skipped  Mismatch    FAILED    Extras
+    Dirs :         1         0         1         0         0         0
+   Files :         2         2         0         0         0         0
+   Bytes :      4096      4096         0         0         0         0
+   Times :   0:00:07   0:00:06                       0:00:00   0:00:01
+";
+
+    #[test]
+    fn execute_captured_reads_bytes_and_duration() {
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+
+        let result = fake_command(&fake).execute_captured().unwrap();
+        assert!(matches!(result.code, OkExitCode::SOME_COPIES));
+        assert_eq!((result.files_copied, result.bytes_copied), (2, 4096));
+        assert_eq!(result.duration, std::time::Duration::from_secs(7));
+
+        let without_times = SUMMARY.lines().filter(|line| !line.contains("Times")).collect::<Vec<&str>>().join("\n");
+        let fake = FakeRobocopy::new(&without_times, 1);
+        let result = fake_command(&fake).execute_captured().unwrap();
+        assert_eq!(result.bytes_copied, 4096);
+        assert!(result.duration < std::time::Duration::from_secs(7));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn captured_execution_records_statistics() {
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+
+        fake_command(&fake).execute_captured().unwrap();
+
+        assert!(logs_contain("robocopy report files_copied=2 bytes_copied=4096"));
+    }
+
+    #[test]
+    fn captured_output_forces_full_paths_once() {
+        let fake = FakeRobocopy::new(SUMMARY, 0);
+
+        let mut command = fake_command(&fake);
+        command.output().unwrap();
+        command.output().unwrap();
+
+        let calls = fake.calls();
+        assert_eq!(calls.len(), 2);
+        assert!(calls.iter().all(|call| call.ends_with(" /fp")), "{:?}", calls);
+        assert_eq!(args(&command).iter().filter(|arg| *arg == "/fp").count(), 1);
+    }
+
+    #[test]
+    fn program_is_the_configured_robocopy_path() {
+        let builder = RobocopyCommandBuilder {
+            robocopy_path: Some(Path::new("C:/Tools/robocopy.exe")),
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            ..RobocopyCommandBuilder::default()
+        };
+        assert_eq!(builder.build().command.get_program(), "C:/Tools/robocopy.exe");
+
+        let default = RobocopyCommandBuilder { robocopy_path: None, ..builder };
+        assert_eq!(default.build().command.get_program(), "robocopy");
+    }
+
+    #[test]
+    fn default_builder_misses_paths() {
+        assert_eq!(RobocopyCommandBuilder::default().try_build().unwrap_err(), BuildError::MissingPaths);
+        assert_eq!(RobocopyCommandBuilder::default().validate(), Err(BuildError::MissingPaths));
+    }
+
+    #[test]
+    fn streaming_report_classifies_lines_and_progress() {
+        let output = format!("\t    New File  \t\t    4096\tC:\\source\\a.txt\n  0%\r 50%\r100%\r\n{}", SUMMARY);
+        let fake = FakeRobocopy::new(&output, 1);
+
+        let mut progress = Vec::new();
+        let mut lines = Vec::new();
+        let mut command = fake_command(&fake);
+        let (code, report) = command.execute_streaming_report(|event| match event {
+            ProgressEvent::Progress(percentage) => progress.push(percentage),
+            ProgressEvent::Line(line) => lines.push(line.to_owned()),
+        }).unwrap();
+
+        assert!(matches!(code, OkExitCode::SOME_COPIES));
+        assert_eq!(report.files.copied, 2);
+        assert_eq!(progress, [0.0, 50.0, 100.0]);
+        assert!(lines[0].contains("C:\\source\\a.txt"));
+        assert!(lines.iter().any(|line| line.contains("Files :")));
+        assert_eq!(fake.calls().len(), 1);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn streaming_execution_records_each_line() {
+        let fake = FakeRobocopy::new(&format!("\t    New File  \t\t    4096\tC:\\source\\a.txt\n{}", SUMMARY), 1);
+
+        fake_command(&fake).execute_streaming_report(|_| {}).unwrap();
+
+        assert!(logs_contain("DEBUG"));
+        assert!(logs_contain("C:\\source\\a.txt"));
+        assert!(logs_contain("Files :         2         2"));
+    }
+
+    #[test]
+    fn adding_empty_post_copy_actions_produces_no_args() {
+        let empty = PostCopyActions::AddAttribsToFiles(FileAttributes::none()) + PostCopyActions::RmvAttribsFromFiles(FileAttributes::none());
+        assert!(Vec::<OsString>::from(empty).is_empty());
+        assert!(empty.single_variants().is_empty());
+
+        assert!(PostCopyActions::AddAttribsToFiles(FileAttributes::none()).merge(PostCopyActions::RmvAttribsFromFiles(FileAttributes::none())).is_none());
+        assert!(PostCopyActions::new(FileAttributes::none(), FileAttributes::none()).is_none());
+    }
+
+    #[test]
+    fn post_copy_actions_add_and_remove() {
+        let actions = PostCopyActions::new(FileAttributes::READ_ONLY, FileAttributes::ARCHIVE).unwrap();
+        assert_eq!(Vec::<OsString>::from(actions), ["/a+:R", "/a-:A"]);
+
+        let merged = PostCopyActions::AddAttribsToFiles(FileAttributes::HIDDEN) + actions;
+        assert_eq!(Vec::<OsString>::from(merged), ["/a+:RH", "/a-:A"]);
+    }
+
+    #[test]
+    fn predicates() {
+        let copy = RobocopyCommandBuilder { source: Path::new("source"), destination: Path::new("destination"), ..RobocopyCommandBuilder::default() };
+        assert!(!copy.is_destructive() && !copy.is_mirror() && !copy.is_move() && !copy.copies_security() && !copy.is_list_only());
+
+        let purge = RobocopyCommandBuilder { remove_files_and_dirs_not_in_src: true, ..copy.clone() };
+        assert!(purge.is_destructive() && !purge.is_mirror());
+
+        let mirror = RobocopyCommandBuilder { subdir_mode: SubdirMode::INCLUDING_EMPTY, ..purge };
+        assert!(mirror.is_destructive() && mirror.is_mirror());
+
+        let mv = RobocopyCommandBuilder { mv: Some(Move::FILES), ..copy.clone() };
+        assert!(mv.is_destructive() && mv.is_move());
+
+        let create = RobocopyCommandBuilder { structure_and_size_zero_files_only: true, ..copy.clone() };
+        assert!(create.is_destructive() && !create.is_move());
+
+        let security = RobocopyCommandBuilder { copy_file_properties: Some(FileProperties::DATA + FileProperties::OWNER_INFO), ..copy.clone() };
+        assert!(security.copies_security());
+        let no_security = RobocopyCommandBuilder { copy_file_properties: Some(FileProperties::DATA + FileProperties::TIME_STAMPS), ..copy.clone() };
+        assert!(!no_security.copies_security());
+
+        let list = RobocopyCommandBuilder { logging: Some(LoggingOptions { only_log: true, ..LoggingOptions::default() }), ..copy };
+        assert!(list.is_list_only() && !list.is_destructive());
+    }
+
+    #[test]
+    fn rejects_create_with_purge() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            structure_and_size_zero_files_only: true,
+            remove_files_and_dirs_not_in_src: true,
+            ..RobocopyCommandBuilder::default()
+        };
+        assert_eq!(builder.validate(), Err(BuildError::CreateWithPurge));
+        assert_eq!(args(&builder.build()), ["source", "destination", "/purge", "/create"]);
+
+        let create = RobocopyCommandBuilder { remove_files_and_dirs_not_in_src: false, ..builder };
+        assert_eq!(create.validate(), Ok(()));
+    }
+
+    #[test]
+    fn bug_report_contains_version_and_args() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            subdir_mode: SubdirMode::NON_EMPTY,
+            ..RobocopyCommandBuilder::default()
+        };
+        let report = builder.bug_report();
+
+        assert!(report.starts_with("```\n") && report.ends_with("\n```"));
+        assert!(report.contains(&format!("robocopyrs {}", env!("CARGO_PKG_VERSION"))));
+        assert!(report.contains(r#"Arguments:
+["source", "destination", "/s"]"#), "{}", report);
+        assert!(report.contains("subdir_mode: NON_EMPTY"));
+    }
+
+    #[test]
+    fn quotes_args_with_spaces_and_trailing_backslashes() {
+        assert_eq!(quote_arg("C:\\source"), "C:\\source");
+        assert_eq!(quote_arg("C:\\My Documents\\"), "\"C:\\My Documents\\\\\"");
+        assert_eq!(quote_arg("C:\\a b\\\\"), "\"C:\\a b\\\\\\\\\"");
+        assert_eq!(quote_arg("say \"hi\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(quote_arg("a\\\"b"), "\"a\\\\\\\"b\"");
+        assert_eq!(quote_arg(""), "\"\"");
+    }
+
+    #[test]
+    fn command_line_quotes_paths() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("C:\\My Documents\\"),
+            destination: Path::new("D:\\backup"),
+            ..RobocopyCommandBuilder::default()
+        };
+        assert_eq!(builder.build().to_command_line(), "robocopy \"C:\\My Documents\\\\\" D:\\backup");
+    }
+
+    #[test]
+    fn failure_keeps_partial_summary() {
+        let partial = SUMMARY.replace("   Files :         2         2         0         0         0", "   Files :         2         1         0         0         1");
+        let fake = FakeRobocopy::new(&format!("2024/03/15 10:00:00 ERROR 32 (0x00000020) Copying File C:\\source\\locked.db\n{}", partial), 9);
+
+        match fake_command(&fake).output() {
+            Err(Error::ExitCodeWithReport { code: ErrExitCode::SOME_COPIES_FAIL, report }) => {
+                assert_eq!((report.files.copied, report.files.failed), (1, 1));
+            },
+            result => panic!("expected a failure with a report, got {:?}", result)
+        }
+
+        let fake = FakeRobocopy::new("", 16);
+        assert!(matches!(fake_command(&fake).output(), Err(Error::ExitCode(ErrExitCode::NO_CHANGE_FATAL_ERROR))));
+    }
+
+    #[test]
+    fn warns_about_long_paths_when_disabled() {
+        let dir = TempDir::new();
+        let deep = dir.path().join("a".repeat(100)).join("b".repeat(100)).join("c".repeat(100));
+        std::fs::create_dir_all(&deep).unwrap();
+        let builder = RobocopyCommandBuilder { source: dir.path(), destination: Path::new("destination"), ..RobocopyCommandBuilder::default() };
+
+        match builder.ensure_long_paths_with(|| false) {
+            Err(BuildError::LongPathsDisabled { path }) => assert!(path.as_os_str().len() >= MAX_PATH && deep.starts_with(&path)),
+            result => panic!("expected long paths to be reported, got {:?}", result)
+        }
+        assert_eq!(builder.ensure_long_paths_with(|| true), Ok(()));
+
+        let disabled_by_option = RobocopyCommandBuilder { filesystem_options: Some(FilesystemOptions::DISABLE_LONG_PATHS), ..builder.clone() };
+        assert!(matches!(disabled_by_option.ensure_long_paths_with(|| true), Err(BuildError::LongPathsDisabled { .. })));
+    }
+
+    #[test]
+    fn short_paths_dont_read_the_registry() {
+        let dir = TempDir::new();
+        dir.write("short/file.txt", "");
+        let builder = RobocopyCommandBuilder { source: dir.path(), destination: Path::new("destination"), ..RobocopyCommandBuilder::default() };
+
+        assert_eq!(builder.ensure_long_paths_with(|| panic!("the registry shouldn't be read")), Ok(()));
+    }
+
+    #[test]
+    fn safe_one_way_sync_preset() {
+        let builder = RobocopyCommandBuilder::safe_one_way_sync(Path::new("source"), Path::new("destination"));
+
+        assert_eq!(args(&builder.build()), ["source", "destination", "/e", "/xo", "/r:3", "/w:10"]);
+        assert!(!builder.is_destructive());
+        assert!(!builder.is_mirror());
+        assert_eq!(builder.validate(), Ok(()));
+    }
+
+    #[test]
+    fn counts_file_attributes() {
+        assert_eq!(FileAttributes::all().count(), 8);
+        assert_eq!((FileAttributes::HIDDEN + FileAttributes::SYSTEM).count(), 2);
+        assert!(FileAttributes::none().is_empty());
+        assert!(!FileAttributes::TEMPORARY.is_empty());
+    }
+
+    #[test]
+    fn file_attributes_contains() {
+        let attribs = FileAttributes::HIDDEN + FileAttributes::SYSTEM;
+        assert!(attribs.contains(FileAttributes::HIDDEN));
+        assert!(!attribs.contains(FileAttributes::READ_ONLY));
+        assert!(FileAttributes::all().contains(attribs));
+    }
+
+    #[test]
+    fn file_attributes_add_and_sub() {
+        assert_eq!(OsString::from(FileAttributes::READ_ONLY + FileAttributes::TEMPORARY), "RT");
+        assert_eq!(OsString::from((FileAttributes::READ_ONLY + FileAttributes::HIDDEN) + (FileAttributes::SYSTEM + FileAttributes::HIDDEN)), "RSH");
+        assert_eq!((FileAttributes::all() - FileAttributes::ENCRYPTED).count(), 7);
+    }
+
+    const RECORDS: &str = "\
+\t    New File  \t\t    4096\tC:\\source\\a.txt
+\t    Newer     \t\t    2048\tC:\\source\\b.txt
+\t      same    \t\t    1024\tC:\\source\\c.txt
+";
+
+    #[test]
+    fn execute_records_runs_a_parseable_copy() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(&format!("{}{}", RECORDS, SUMMARY), 1);
+        let log = dir.path().join("robocopy.log");
+        let builder = RobocopyCommandBuilder {
+            logging: Some(LoggingOptions {
+                dont_log_file_names: true,
+                dont_log_summary: true,
+                log_file: Some(logging::LogFileSettings { log: &log, unicode: false, append: false, max_bytes: None }),
+                ..LoggingOptions::default()
+            }),
+            ..fake_builder(&fake, &dir)
+        };
+        let mut command = builder.build();
+        let original_args = args(&command);
+
+        let (code, records) = command.execute_records().unwrap();
+        assert!(matches!(code, OkExitCode::SOME_COPIES));
+        assert_eq!(records.iter().map(|record| record.class).collect::<Vec<_>>(), [FileClass::NEW_FILE, FileClass::NEWER, FileClass::SAME]);
+        assert_eq!(args(&command), original_args);
+
+        let call = &fake.calls()[0];
+        assert!(!call.contains("/nfl") && !call.contains("/njs"), "{}", call);
+        assert!(call.contains(&format!("/log:{}", log.display())) && call.contains("/tee"), "{}", call);
+        assert!(call.ends_with("/v /bytes /np /fp"), "{}", call);
+    }
+
+    #[test]
+    fn execute_records_fails_without_summary_or_records() {
+        let dir = TempDir::new();
+
+        let fake = FakeRobocopy::new(RECORDS, 1);
+        assert!(matches!(fake_builder(&fake, &dir).build().execute_records(), Err(Error::ReportParse { .. })));
+
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+        match fake_builder(&fake, &dir).build().execute_records() {
+            Err(Error::ReportParse { context, .. }) => assert!(context.contains("no file record"), "{}", context),
+            result => panic!("expected a parse error, got {:?}", result)
+        }
+    }
+
+    const EXTRA_RECORDS: &str = "\
+\t*EXTRA File  \t\t     128\tC:\\destination\\a.txt
+\t*EXTRA File  \t\t     128\tC:\\destination\\b.txt
+\t*EXTRA Dir        -1\tC:\\destination\\old\\
+";
+
+    #[test]
+    fn mirror_guarded_aborts_before_the_real_run() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::with_dry_run(&format!("{}{}", EXTRA_RECORDS, SUMMARY), SUMMARY, 1);
+        let mut command = fake_builder(&fake, &dir).build();
+
+        match command.execute_mirror_guarded(2) {
+            Err(Error::TooManyDeletions { deletions, max_deletions }) => assert_eq!((deletions, max_deletions), (3, 2)),
+            result => panic!("expected too many deletions, got {:?}", result)
+        }
+
+        let calls = fake.calls();
+        assert_eq!(calls.len(), 1, "{:?}", calls);
+        assert!(calls[0].contains(" /mir ") && calls[0].contains(" /l "), "{}", calls[0]);
+    }
+
+    #[test]
+    fn mirror_guarded_runs_within_the_threshold() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::with_dry_run(&format!("{}{}", EXTRA_RECORDS, SUMMARY), SUMMARY, 1);
+
+        let mut command = fake_builder(&fake, &dir).build();
+        command.command.stdout(Stdio::null());
+
+        assert!(matches!(command.execute_mirror_guarded(3), Ok(OkExitCode::SOME_COPIES)));
+        assert_eq!(fake.calls().len(), 2);
+        assert!(!fake.calls()[1].contains(" /l "));
+    }
+
+    #[test]
+    fn mirror_guarded_fails_closed_on_unparseable_dry_run() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::with_dry_run(EXTRA_RECORDS, SUMMARY, 1);
+
+        assert!(matches!(fake_builder(&fake, &dir).build().execute_mirror_guarded(10), Err(Error::ReportParse { .. })));
+        assert_eq!(fake.calls().len(), 1);
+    }
+
+    #[test]
+    fn dry_run_drops_logging_and_hiding_options() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::with_dry_run(&format!("{}{}", EXTRA_RECORDS, SUMMARY), SUMMARY, 1);
+        let log = dir.path().join("robocopy.log");
+        let builder = RobocopyCommandBuilder {
+            logging: Some(LoggingOptions {
+                dont_log_file_names: true,
+                dont_log_dir_names: true,
+                combination_log: true,
+                log_file: Some(logging::LogFileSettings { log: &log, unicode: false, append: true, max_bytes: None }),
+                ..LoggingOptions::default()
+            }),
+            ..fake_builder(&fake, &dir)
+        };
+
+        let mut command = builder.build();
+        command.command.stdout(Stdio::null());
+        command.execute_mirror_guarded(3).unwrap();
+
+        let calls = fake.calls();
+        for option in ["/log", "/tee", "/nfl", "/ndl"] {
+            assert!(!calls[0].contains(option), "{}", calls[0]);
+        }
+        assert!(calls[1].contains("/log+:") && calls[1].contains("/nfl"), "{}", calls[1]);
+    }
+
+    #[test]
+    fn canonicalize_collapses_single_file_attribute() {
+        let mut flags = [false; 8];
+        flags[3] = true;
+
+        assert_eq!(FileAttributes::_MULTIPLE(flags).canonicalize(), FileAttributes::HIDDEN);
+        assert_eq!(FileAttributes::_MULTIPLE([false; 8]).canonicalize(), FileAttributes::none());
+        assert_eq!((FileAttributes::HIDDEN + FileAttributes::SYSTEM).canonicalize().count(), 2);
+    }
+
+    #[test]
+    fn incremental_without_marker_copies_everything() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new("", 1);
+        let marker = dir.path().join("marker");
+
+        let before = filter::robocopy_date_since(SystemTime::now());
+        assert!(matches!(fake_builder(&fake, &dir).build().execute_incremental(&marker), Ok(OkExitCode::SOME_COPIES)));
+        let after = filter::robocopy_date_since(SystemTime::now());
+
+        assert!(!fake.calls()[0].contains("/maxage"), "{}", fake.calls()[0]);
+        let date = std::fs::read_to_string(&marker).unwrap();
+        assert!(date == before || date == after, "{}", date);
+    }
+
+    #[test]
+    fn incremental_with_marker_sets_max_age() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new("", 0);
+        let marker = dir.write("marker", "20240314\n");
+
+        fake_builder(&fake, &dir).build().execute_incremental(&marker).unwrap();
+
+        assert!(fake.calls()[0].contains("/maxage:20240314"), "{}", fake.calls()[0]);
+        assert_ne!(std::fs::read_to_string(&marker).unwrap(), "20240314\n");
+    }
+
+    #[test]
+    fn incremental_keeps_marker_on_failure() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new("", 8);
+        let marker = dir.write("marker", "20240314");
+
+        assert!(matches!(fake_builder(&fake, &dir).build().execute_incremental(&marker), Err(Error::ExitCode(ErrExitCode::FAIL))));
+        assert_eq!(std::fs::read_to_string(&marker).unwrap(), "20240314");
+    }
+
+    #[test]
+    fn rotates_oversized_log_before_running() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+        let log = dir.write("robocopy.log", "previous run\n");
+        let previous = dir.write("robocopy.log.1", "older run\n");
+        let builder = RobocopyCommandBuilder {
+            logging: Some(LoggingOptions {
+                log_file: Some(logging::LogFileSettings { log: &log, unicode: false, append: true, max_bytes: Some(4) }),
+                ..LoggingOptions::default()
+            }),
+            ..fake_builder(&fake, &dir)
+        };
+
+        builder.build().execute_captured().unwrap();
+
+        assert!(!log.exists());
+        assert_eq!(std::fs::read_to_string(previous).unwrap(), "previous run\n");
+        assert!(fake.calls()[0].contains("/log+:"), "{}", fake.calls()[0]);
+    }
+
+    #[test]
+    fn to_command_spawns_without_consuming() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+        let command = fake_builder(&fake, &dir).build();
+
+        let output = command.to_command().env("ROBOCOPYRS_TEST", "1").stdout(Stdio::piped()).output().unwrap();
+
+        assert_eq!(output.status.code(), Some(1));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), SUMMARY);
+        assert_eq!(fake.calls(), [args(&command).join(" ")]);
+        assert!(command.command.get_envs().next().is_none());
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn execute_verified_reports_corrupted_destination() {
+        let dir = TempDir::new();
+        let source = dir.path().join("source");
+        let destination = dir.path().join("destination");
+        let intact = dir.write("source/intact.txt", "intact");
+        let corrupted = dir.write("source/corrupted.txt", "original");
+        dir.write("destination/intact.txt", "intact");
+        dir.write("destination/corrupted.txt", "0riginal");
+        let records = format!(
+            "\t    New File  \t\t       6\t{}\n\t    New File  \t\t       8\t{}\n{}",
+            intact.display(), corrupted.display(), SUMMARY
+        );
+        let fake = FakeRobocopy::new(&records, 1);
+        let builder = RobocopyCommandBuilder { source: &source, destination: &destination, ..fake_builder(&fake, &dir) };
+
+        let report = builder.build().execute_verified(verify::HashAlgo::SHA256).unwrap();
+
+        assert!(matches!(report.code, OkExitCode::SOME_COPIES));
+        assert_eq!(report.verified, 1);
+        assert_eq!(report.mismatches, [destination.join("corrupted.txt")]);
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn execute_verified_rejects_moves() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+        let builder = RobocopyCommandBuilder { mv: Some(Move::FILES), ..fake_builder(&fake, &dir) };
+
+        assert!(matches!(builder.build().execute_verified(verify::HashAlgo::SHA256), Err(Error::VerifyMovedFiles)));
+        assert!(fake.calls().is_empty());
+    }
+
+    #[cfg(feature = "verify")]
+    #[test]
+    fn execute_verified_fails_without_records() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(SUMMARY, 1);
+
+        assert!(matches!(fake_builder(&fake, &dir).build().execute_verified(verify::HashAlgo::SHA256), Err(Error::ReportParse { .. })));
+    }
+
+    #[test]
+    fn no_directory_properties_emits_nodcopy() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            copy_dir_properties: Some(DirectoryProperties::none()),
+            ..RobocopyCommandBuilder::default()
+        };
+        let args = args(&builder.build());
+
+        assert!(args.iter().any(|arg| arg == "/nodcopy"), "{:?}", args);
+        assert!(!args.iter().any(|arg| arg.starts_with("/dcopy")), "{:?}", args);
+    }
+
+    const DIFF_RECORDS: &str = "\
+\t    New File  \t\t    4096\tC:\\source\\new.txt
+\t    Newer     \t\t     512\tC:\\source\\newer.txt
+\t    Older     \t\t     256\tC:\\source\\older.txt
+\t      same    \t\t    1024\tC:\\source\\same.txt
+\t*EXTRA File  \t\t     128\tC:\\destination\\extra.txt
+";
+
+    #[test]
+    fn diff_classifies_the_dry_run() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(&format!("{}{}", DIFF_RECORDS, SUMMARY), 3);
+
+        let diff = fake_builder(&fake, &dir).build().diff().unwrap();
+
+        assert_eq!(diff.new, [PathBuf::from("C:\\source\\new.txt")]);
+        assert_eq!(diff.modified, [PathBuf::from("C:\\source\\newer.txt"), PathBuf::from("C:\\source\\older.txt")]);
+        assert_eq!(diff.extra, [PathBuf::from("C:\\destination\\extra.txt")]);
+        assert_eq!(diff.total_bytes, 4096 + 512 + 256);
+        assert!(fake.calls()[0].contains(" /l /v /bytes /ndl /np"), "{}", fake.calls()[0]);
+    }
+
+    #[test]
+    fn diff_skips_excluded_classes() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(&format!("{}{}", DIFF_RECORDS, SUMMARY), 3);
+        let builder = RobocopyCommandBuilder {
+            filter: Some(Filter { file_exclusion_filter: Some(FileExclusionFilter::OLDER), ..Filter::default() }),
+            ..fake_builder(&fake, &dir)
+        };
+
+        let diff = builder.build().diff().unwrap();
+
+        assert_eq!(diff.modified, [PathBuf::from("C:\\source\\newer.txt")]);
+        assert_eq!(diff.total_bytes, 4096 + 512);
+    }
+
+    #[test]
+    fn include_extensions_adds_wildcards() {
+        let mut builder = RobocopyCommandBuilder { source: Path::new("source"), destination: Path::new("destination"), ..RobocopyCommandBuilder::default() };
+        builder.files.push(Cow::Borrowed("readme"));
+        builder.include_extensions(&["txt", ".md"]);
+
+        assert_eq!(builder.files, ["readme", "*.txt", "*.md"]);
+        assert_eq!(args(&builder.try_build().unwrap())[2..], ["readme", "*.txt", "*.md"]);
+    }
+
+    #[test]
+    fn estimate_normalizes_suffixed_sizes() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(&SUMMARY.replace("   Bytes :      4096      4096", "   Bytes :    1.50 g   512.0 m"), 1);
+
+        let estimate = fake_builder(&fake, &dir).build().estimate().unwrap();
+
+        assert_eq!(estimate, Estimate { total_files: 2, total_bytes: 536_870_912 });
+    }
+
+    #[test]
+    fn captured_result_reports_backup_fallback() {
+        let dir = TempDir::new();
+        let fallback = FakeRobocopy::new(&format!("Restarting in Backup mode...\n{}", SUMMARY), 1);
+        let restartable = FakeRobocopy::new(SUMMARY, 1);
+        let zb = |fake| RobocopyCommandBuilder { copy_mode: Some(CopyMode::RESTARTABLE_MODE_BACKUP_MODE_FALLBACK), ..fake_builder(fake, &dir) };
+
+        assert!(zb(&fallback).build().execute_captured().unwrap().used_backup_mode);
+        assert!(!zb(&restartable).build().execute_captured().unwrap().used_backup_mode);
+    }
+
+    #[test]
+    fn try_build_validates_file_patterns() {
+        let builder = |file: &'static str| RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            files: vec![Cow::Borrowed(file)],
+            ..RobocopyCommandBuilder::default()
+        };
+
+        assert_eq!(builder("/e").try_build().unwrap_err(), BuildError::Pattern(PatternError::LooksLikeOption(String::from("/e"))));
+        assert!(builder("*.rs").try_build().is_ok());
+    }
+
+    #[test]
+    fn effective_config_fills_robocopy_defaults() {
+        let builder = RobocopyCommandBuilder { source: Path::new("source"), destination: Path::new("destination"), ..RobocopyCommandBuilder::default() };
+        let config = builder.effective_config();
+
+        assert_eq!(config.retries, 1_000_000);
+        assert_eq!(config.wait, 30);
+        assert_eq!(config.copy_file_properties, FileProperties::DATA + FileProperties::ATTRIBUTES + FileProperties::TIME_STAMPS);
+        assert_eq!(config.copy_dir_properties, DirectoryProperties::DATA + DirectoryProperties::ATTRIBUTES);
+        assert_eq!((config.threads, config.inter_packet_gap), (1, 0));
+
+        let robocopy_defaults = RobocopyCommandBuilder {
+            retry_settings: Some(RetrySettings { specify_retries_failed_copies: Some(None), specify_wait_between_retries: Some(None), ..RetrySettings::default() }),
+            performance_options: Some(PerformanceOptions { performance_choice: Some(PerformanceChoice::Threads(None)), ..PerformanceOptions::default() }),
+            ..builder.clone()
+        }.effective_config();
+        assert_eq!((robocopy_defaults.retries, robocopy_defaults.wait, robocopy_defaults.threads), (1_000_000, 30, 8));
+
+        let explicit = RobocopyCommandBuilder { retry_settings: Some(RetrySettings::with(3, 10)), ..builder }.effective_config();
+        assert_eq!((explicit.retries, explicit.wait), (3, 10));
+    }
+
+    #[test]
+    fn exit_code_out_of_i8_range_is_kept() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new("", 200);
+
+        assert!(matches!(fake_builder(&fake, &dir).build().execute(), Err(Error::ExitCode(ErrExitCode::INVALID_EXIT_CODE(200)))));
+    }
+
+    #[test]
+    fn subdir_modes_arguments() {
+        let builder = |subdir_mode| RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            subdir_mode,
+            ..RobocopyCommandBuilder::default()
+        };
+
+        assert_eq!(args(&builder(SubdirMode::NONE).build()), ["source", "destination"]);
+        assert_eq!(args(&builder(SubdirMode::NON_EMPTY).build()), ["source", "destination", "/s"]);
+        assert_eq!(args(&builder(SubdirMode::INCLUDING_EMPTY).build()), ["source", "destination", "/e"]);
+        assert_eq!(RobocopyCommandBuilder::default().subdir_mode, SubdirMode::NONE);
+    }
+
+    #[test]
+    fn success_codes_accept_a_failure_code() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new("", 8);
+        let mut builder = fake_builder(&fake, &dir);
+        builder.success_codes(&[8]);
+
+        assert_eq!(builder.build().execute().unwrap(), OkExitCode::ACCEPTED(8));
+    }
+
+    #[test]
+    fn success_codes_dont_apply_to_dry_runs() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::new(&format!("{}{}", DIFF_RECORDS, SUMMARY), 3);
+        let mut builder = fake_builder(&fake, &dir);
+        builder.success_codes(&[0]);
+
+        assert_eq!(builder.build().diff().unwrap().new, [PathBuf::from("C:\\source\\new.txt")]);
+        let mut command = builder.build();
+        command.command.stdout(Stdio::null());
+        assert!(matches!(command.execute(), Err(Error::RejectedExitCode(OkExitCode::SOME_COPIES_EXTRA_FOUND))));
+    }
+
+    #[test]
+    fn declined_confirmation_copies_nothing() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::with_dry_run(&format!("{}{}", EXTRA_RECORDS, SUMMARY), SUMMARY, 1);
+        let builder = RobocopyCommandBuilder { remove_files_and_dirs_not_in_src: true, ..fake_builder(&fake, &dir) };
+        let mut summaries = Vec::new();
+
+        let result = builder.build().execute_with_confirm(|summary| {
+            summaries.push(*summary);
+            false
+        });
+
+        assert!(matches!(result, Err(Error::NotConfirmed)));
+        assert_eq!(summaries, [DestructiveSummary { deletions: 3, moves: 0 }]);
+        let calls = fake.calls();
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].contains(" /l "), "{}", calls[0]);
+    }
+
+    #[test]
+    fn confirmation_fails_closed_on_unparseable_dry_run() {
+        let dir = TempDir::new();
+        let fake = FakeRobocopy::with_dry_run(EXTRA_RECORDS, SUMMARY, 1);
+        let builder = RobocopyCommandBuilder { mv: Some(Move::FILES), ..fake_builder(&fake, &dir) };
+
+        let result = builder.build().execute_with_confirm(|_| panic!("nothing to confirm without a parsed dry run"));
+
+        assert!(matches!(result, Err(Error::ReportParse { .. })));
+        assert_eq!(fake.calls().len(), 1);
+    }
+
+    #[test]
+    fn merge_overlay_takes_precedence() {
+        let base = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            files: vec![Cow::Borrowed("*.txt"), Cow::Borrowed("*.md")],
+            copy_mode: Some(CopyMode::RESTARTABLE_MODE),
+            retry_settings: Some(RetrySettings::with(3, 10)),
+            copy_file_properties: Some(FileProperties::DATA),
+            ..RobocopyCommandBuilder::default()
+        };
+        let overlay = RobocopyCommandBuilder {
+            destination: Path::new("other"),
+            files: vec![Cow::Borrowed("*.md"), Cow::Borrowed("*.rs")],
+            retry_settings: Some(RetrySettings::no_retry()),
+            copy_file_properties: Some(FileProperties::TIME_STAMPS),
+            remove_files_and_dirs_not_in_src: true,
+            ..RobocopyCommandBuilder::default()
+        };
+
+        let merged = base.merge(&overlay);
+
+        assert_eq!(merged.source, Path::new("source"));
+        assert_eq!(merged.destination, Path::new("other"));
+        assert_eq!(merged.files, ["*.txt", "*.md", "*.rs"]);
+        assert_eq!(merged.copy_mode, Some(CopyMode::RESTARTABLE_MODE));
+        assert_eq!(merged.retry_settings, Some(RetrySettings::no_retry()));
+        assert_eq!(merged.copy_file_properties, Some(FileProperties::DATA + FileProperties::TIME_STAMPS));
+        assert!(merged.remove_files_and_dirs_not_in_src);
+    }
+
+    #[test]
+    fn merge_with_default_overlay_keeps_base() {
+        let base = RobocopyCommandBuilder::safe_one_way_sync(Path::new("source"), Path::new("destination"));
+
+        assert_eq!(base.merge(&RobocopyCommandBuilder::default()), base);
+        assert_eq!(RobocopyCommandBuilder::default().merge(&base), base);
+    }
+
+    #[test]
+    fn clone_structure_only_excludes_every_file() {
+        let mut builder = RobocopyCommandBuilder { source: Path::new("source"), destination: Path::new("destination"), ..RobocopyCommandBuilder::default() };
+        builder.clone_structure_only();
+
+        assert_eq!(args(&builder.build()), ["source", "destination", "/e", "/dcopy:DAT", "/xf", "*"]);
+
+        let mut keeping_properties = RobocopyCommandBuilder { copy_dir_properties: Some(DirectoryProperties::DATA), ..builder.clone() };
+        keeping_properties.structure_and_size_zero_files_only = true;
+        keeping_properties.clone_structure_only();
+        let args = args(&keeping_properties.build());
+        assert!(args.contains(&String::from("/dcopy:D")) && !args.contains(&String::from("/create")), "{:?}", args);
+    }
+
+    #[test]
+    fn try_build_rejects_registry_changes_unless_allowed() {
+        let builder = RobocopyCommandBuilder {
+            source: Path::new("source"),
+            destination: Path::new("destination"),
+            retry_settings: Some(RetrySettings { save_specifications: true, ..RetrySettings::with(3, 10) }),
+            ..RobocopyCommandBuilder::default()
+        };
+        assert_eq!(builder.try_build().unwrap_err(), BuildError::ModifiesRegistry);
+
+        let allowed = RobocopyCommandBuilder { allow_registry_changes: true, ..builder.clone() };
+        assert!(args(&allowed.try_build().unwrap()).contains(&String::from("/reg")));
+        assert!(builder.merge(&allowed).try_build().is_ok());
+    }
+
+    #[test]
+    fn resumable_large_transfer_preset() {
+        let builder = RobocopyCommandBuilder::resumable_large_transfer(Path::new("source"), Path::new("destination"));
+
+        assert_eq!(args(&builder.try_build().unwrap()), ["source", "destination", "/z", "/e", "/compress", "/r:10", "/w:30"]);
+    }
+
+    #[test]
+    fn try_build_rejects_unbuffered_restartable_mode() {
+        let restartable = RobocopyCommandBuilder::resumable_large_transfer(Path::new("source"), Path::new("destination"));
+
+        for copy_mode in [CopyMode::RESTARTABLE_MODE, CopyMode::RESTARTABLE_MODE_BACKUP_MODE_FALLBACK] {
+            let unbuffered = RobocopyCommandBuilder { unbuffered: true, copy_mode: Some(copy_mode), ..restartable.clone() };
+            assert_eq!(unbuffered.try_build().unwrap_err(), BuildError::UnbufferedRestartable);
+        }
+
+        let backup = RobocopyCommandBuilder { unbuffered: true, copy_mode: Some(CopyMode::BACKUP_MODE), ..restartable };
+        assert!(backup.try_build().is_ok());
+    }
+}
@@ -1,9 +1,10 @@
 //! Logging Options
 
-use std::{ffi::OsString, path::Path};
+use std::{ffi::OsString, fs, io, path::Path};
+use thiserror::Error;
 
 /// Log file settings
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LogFileSettings<'a> {
     /// Path to the log file
     pub log: &'a Path,
@@ -13,9 +14,27 @@ pub struct LogFileSettings<'a> {
     pub unicode: bool,
     /// Appends output to the existing log file.
     pub append: bool,
+    /// Rotates the log file before running when it's larger than `max_bytes`.
+    /// 
+    /// The log is renamed with a `.1` suffix, replacing the previous rotated log, and robocopy starts a new one.
+    pub max_bytes: Option<u64>,
+}
+
+/// Renames `log` with a `.1` suffix if it's larger than `max_bytes`
+pub(crate) fn rotate_log(log: &Path, max_bytes: u64) -> io::Result<()> {
+    match fs::metadata(log) {
+        Ok(metadata) if metadata.len() > max_bytes => {
+            let mut rotated = log.as_os_str().to_owned();
+            rotated.push(".1");
+            fs::rename(log, rotated)
+        },
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err)
+    }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 /// Specify the logging options
 pub struct LoggingOptions<'a> {
     /// Specifies that files are to be listed only (and not copied, deleted, or time stamped).
@@ -86,6 +105,50 @@ pub struct LoggingOptions<'a> {
     pub unicode: bool
 }
 
+/// A contradictory combination of [LoggingOptions]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LoggingError {
+    /// A file detail is requested while file names aren't logged (`/nfl`), so no file line is printed
+    #[error("{0} has no effect when file names are not logged (/nfl)")]
+    FileDetailsWithoutFileNames(&'static str),
+    /// The output is written to the console and to a log file (`/tee`) without a log file
+    #[error("/tee requires a log file")]
+    TeeWithoutLogFile,
+    /// The estimated time of arrival (`/eta`) is shown with the progress, which is disabled (`/np`)
+    #[error("/eta has no effect when the progress is not displayed (/np)")]
+    EtaWithoutProgress,
+    /// Files are only listed (`/l`) while the output is written to a log file, which would describe a copy that didn't happen
+    #[error("/l only lists the files, logging it to a file is likely a mistake")]
+    OnlyLogWithLogFile,
+}
+
+impl<'a> LoggingOptions<'a> {
+    /// Checks that the options don't contradict each other.
+    pub fn validate(&self) -> Result<(), LoggingError> {
+        if self.dont_log_file_names {
+            let file_details = [
+                (self.verbose, "/v"),
+                (self.time_stamps, "/ts"),
+                (self.full_path_names, "/fp"),
+            ];
+            if let Some((_, option)) = file_details.into_iter().find(|(set, _)| *set) {
+                return Err(LoggingError::FileDetailsWithoutFileNames(option));
+            }
+        }
+        if self.combination_log && self.log_file.is_none() {
+            return Err(LoggingError::TeeWithoutLogFile);
+        }
+        if self.show_estimated_time_of_arrival && self.no_progress_display {
+            return Err(LoggingError::EtaWithoutProgress);
+        }
+        if self.only_log && self.log_file.is_some() {
+            return Err(LoggingError::OnlyLogWithLogFile);
+        }
+
+        Ok(())
+    }
+}
+
 impl<'a> From<&'a LogFileSettings<'a>> for OsString {
     fn from(ls: &'a LogFileSettings<'a>) -> Self {
         OsString::from(
@@ -126,4 +189,69 @@ impl<'a> From<&'a LoggingOptions<'a>> for Vec<OsString> {
         if lo.unicode { args.push("/unicode".into()) }
         args
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_file_details_without_file_names() {
+        let options = |verbose, time_stamps, full_path_names| LoggingOptions {
+            dont_log_file_names: true,
+            verbose,
+            time_stamps,
+            full_path_names,
+            ..LoggingOptions::default()
+        };
+
+        assert_eq!(options(true, false, false).validate(), Err(LoggingError::FileDetailsWithoutFileNames("/v")));
+        assert_eq!(options(false, true, false).validate(), Err(LoggingError::FileDetailsWithoutFileNames("/ts")));
+        assert_eq!(options(false, false, true).validate(), Err(LoggingError::FileDetailsWithoutFileNames("/fp")));
+        assert_eq!(options(false, false, false).validate(), Ok(()));
+    }
+
+    #[test]
+    fn accepts_hidden_size_and_class_without_file_names() {
+        let options = LoggingOptions {
+            dont_log_file_names: true,
+            dont_log_size: true,
+            dont_log_class: true,
+            ..LoggingOptions::default()
+        };
+
+        assert_eq!(options.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_other_contradictions() {
+        let log = Path::new("robocopy.log");
+        let log_file = Some(LogFileSettings { log, unicode: false, append: false, max_bytes: None });
+
+        let tee = LoggingOptions { combination_log: true, ..LoggingOptions::default() };
+        assert_eq!(tee.validate(), Err(LoggingError::TeeWithoutLogFile));
+        assert_eq!(LoggingOptions { log_file, ..tee }.validate(), Ok(()));
+
+        let eta = LoggingOptions { show_estimated_time_of_arrival: true, no_progress_display: true, ..LoggingOptions::default() };
+        assert_eq!(eta.validate(), Err(LoggingError::EtaWithoutProgress));
+
+        let only_log = LoggingOptions { only_log: true, ..LoggingOptions::default() };
+        assert_eq!(only_log.validate(), Ok(()));
+        assert_eq!(LoggingOptions { log_file, ..only_log }.validate(), Err(LoggingError::OnlyLogWithLogFile));
+    }
+
+    #[test]
+    fn rotates_only_oversized_logs() {
+        let dir = crate::test_support::TempDir::new();
+        let small = dir.write("small.log", "small");
+        let large = dir.write("large.log", "a log larger than the limit");
+
+        rotate_log(&small, 10).unwrap();
+        rotate_log(&large, 10).unwrap();
+        rotate_log(&dir.path().join("missing.log"), 10).unwrap();
+
+        assert!(small.exists() && !dir.path().join("small.log.1").exists());
+        assert!(!large.exists());
+        assert_eq!(fs::read_to_string(dir.path().join("large.log.1")).unwrap(), "a log larger than the limit");
+    }
+}
@@ -1,21 +1,33 @@
 //! Performance options
 
-use std::ffi::OsString;
+use std::{ffi::OsString, time::Duration};
+use thiserror::Error;
 
 /// Only one Performance choice can be chosen
 #[allow(non_camel_case_types)]
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum PerformanceChoice {
     /// Creates multi-threaded copies with `n` threads. `n` must be an integer between 1 and 128. The default value for `n` is 8.
     /// 
     /// Corresponds to `/mt` option.
     Threads(Option<u8>), // max 128
-    /// Specifies the inter-packet gap to free bandwidth on slow lines.
+    /// Specifies the inter-packet gap to free bandwidth on slow lines, in milliseconds.
+    /// 
+    /// See [PerformanceChoice::inter_packet_gap] to specify it as a [Duration].
     /// 
     /// Corresponds to `/ipg` option.
     InterPacketGap(usize)
 }
 
+impl PerformanceChoice {
+    /// Specifies the inter-packet gap to free bandwidth on slow lines.
+    /// 
+    /// Robocopy expects whole milliseconds, sub-millisecond precision is truncated.
+    pub fn inter_packet_gap(gap: Duration) -> Self {
+        Self::InterPacketGap(gap.as_millis().try_into().unwrap_or(usize::MAX))
+    }
+}
+
 impl From<PerformanceChoice> for OsString {
     fn from(pc: PerformanceChoice) -> Self {
         (&pc).into()
@@ -31,8 +43,19 @@ impl From<&PerformanceChoice> for OsString {
     }
 }
 
+/// An inconsistency found while validating [PerformanceOptions]
+/// 
+/// Multithreading and inter-packet gap are mutually exclusive in robocopy,
+/// this is enforced by [PerformanceChoice] allowing only one of them.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum PerformanceError {
+    /// The number of threads is outside of the 1 to 128 range supported by robocopy
+    #[error("Invalid thread count {0}, must be between 1 and 128")]
+    InvalidThreadCount(u8),
+}
+
 /// Enable performance options
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct PerformanceOptions {
     /// Enables multithreading or inter-packet gap
     pub performance_choice: Option<PerformanceChoice>,
@@ -51,6 +74,18 @@ pub struct PerformanceOptions {
     pub copy_rather_than_follow_link: bool,
 }
 
+impl PerformanceOptions {
+    /// Checks that the options are supported by robocopy.
+    /// 
+    /// Building the command without validation clamps the number of threads to the supported range.
+    pub fn validate(&self) -> Result<(), PerformanceError> {
+        match self.performance_choice {
+            Some(PerformanceChoice::Threads(Some(n))) if !(1..=128).contains(&n) => Err(PerformanceError::InvalidThreadCount(n)),
+            _ => Ok(())
+        }
+    }
+}
+
 impl From<&PerformanceOptions> for Vec<OsString> {
     fn from(po: &PerformanceOptions) -> Self {
         let mut res: Vec<OsString> = Vec::new();
@@ -70,18 +105,28 @@ impl From<PerformanceOptions> for Vec<OsString> {
 }
 
 /// A struct containing retry options
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct RetrySettings {
     /// Specifies the number of retries on failed copies. The default value of n is 1,000,000 (one million retries).
     /// 
+    /// `None` omits the option, `Some(None)` passes it without a value (`/r:`) so that robocopy uses its default,
+    /// and `Some(Some(n))` passes `/r:n`.
+    /// 
     /// Corresponds to `/r` option.
     pub specify_retries_failed_copies: Option<Option<usize>>,
     /// Specifies the wait time between retries, in seconds. The default value of n is 30 (wait time 30 seconds).
     /// 
+    /// `None` omits the option, `Some(None)` passes it without a value (`/w:`) so that robocopy uses its default,
+    /// and `Some(Some(n))` passes `/w:n`.
+    /// 
     /// Corresponds to `/w` option.
     pub specify_wait_between_retries: Option<Option<usize>>,
     /// Saves the values specified in the /r and /w options as default settings in the registry.
     /// 
+    /// **Warning:** the defaults are saved machine-wide and change the retries of every later robocopy run,
+    /// including runs by other programs. [try_build](crate::RobocopyCommandBuilder::try_build) rejects it
+    /// unless [allow_registry_changes](crate::RobocopyCommandBuilder::allow_registry_changes) is set.
+    /// 
     /// Corresponds to `/reg` option.
     pub save_specifications: bool,
     /// Specifies that the system waits for share names to be defined (retry error 67).
@@ -90,6 +135,31 @@ pub struct RetrySettings {
     pub await_share_names_def: bool,
 }
 
+impl RetrySettings {
+    /// Returns true if the settings are saved in the registry as the defaults of robocopy (`/reg`).
+    pub fn will_modify_registry(&self) -> bool {
+        self.save_specifications
+    }
+
+    /// Fails copies on the first error, without retrying nor waiting.
+    /// 
+    /// Corresponds to `/r:0 /w:0` options.
+    pub fn no_retry() -> Self {
+        Self::with(0, 0)
+    }
+
+    /// Retries failed copies `retries` times, waiting `wait_secs` seconds between retries.
+    /// 
+    /// Corresponds to `/r:retries /w:wait_secs` options.
+    pub fn with(retries: usize, wait_secs: usize) -> Self {
+        RetrySettings {
+            specify_retries_failed_copies: Some(Some(retries)),
+            specify_wait_between_retries: Some(Some(wait_secs)),
+            ..Self::default()
+        }
+    }
+}
+
 impl From<&RetrySettings> for Vec<OsString> {
     fn from(rs: &RetrySettings) -> Self {
         let mut result = Vec::new();
@@ -127,3 +197,49 @@ impl From<RetrySettings> for Vec<OsString> {
         (&rs).into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_thread_count_out_of_range() {
+        let threads = |n| PerformanceOptions { performance_choice: Some(PerformanceChoice::Threads(Some(n))), ..PerformanceOptions::default() };
+
+        assert_eq!(threads(0).validate(), Err(PerformanceError::InvalidThreadCount(0)));
+        assert_eq!(threads(129).validate(), Err(PerformanceError::InvalidThreadCount(129)));
+        assert_eq!(threads(1).validate(), Ok(()));
+        assert_eq!(threads(128).validate(), Ok(()));
+    }
+
+    #[test]
+    fn inter_packet_gap_with_compression_is_valid() {
+        let options = PerformanceOptions {
+            performance_choice: Some(PerformanceChoice::InterPacketGap(50)),
+            request_network_compression: true,
+            ..PerformanceOptions::default()
+        };
+
+        assert_eq!(options.validate(), Ok(()));
+        assert_eq!(Vec::<OsString>::from(&options), ["/ipg:50", "/compress"]);
+    }
+
+    #[test]
+    fn inter_packet_gap_from_duration() {
+        assert_eq!(OsString::from(PerformanceChoice::inter_packet_gap(Duration::from_millis(50))), "/ipg:50");
+        assert_eq!(PerformanceChoice::inter_packet_gap(Duration::from_micros(2_500)), PerformanceChoice::InterPacketGap(2));
+    }
+
+    #[test]
+    fn no_retry_sets_zero_retries_and_wait() {
+        assert_eq!(Vec::<OsString>::from(RetrySettings::no_retry()), ["/r:0", "/w:0"]);
+        assert_eq!(Vec::<OsString>::from(RetrySettings::with(3, 5)), ["/r:3", "/w:5"]);
+    }
+
+    #[test]
+    fn only_saving_specifications_modifies_registry() {
+        assert!(RetrySettings { save_specifications: true, ..RetrySettings::default() }.will_modify_registry());
+        assert!(!RetrySettings::default().will_modify_registry());
+        assert!(!RetrySettings { await_share_names_def: true, ..RetrySettings::with(3, 10) }.will_modify_registry());
+    }
+}
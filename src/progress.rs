@@ -0,0 +1,63 @@
+//! Progress of a running robocopy command
+//!
+//! Robocopy redraws the progress of the current file with carriage returns,
+//! every segment of the output ending with `\r` or `\n` is classified as an event.
+
+/// An event emitted while robocopy is running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressEvent<'a> {
+    /// The percentage of the current file that has been copied
+    Progress(f32),
+    /// Any other line of output (file and directory names, job header and summary)
+    Line(&'a str),
+}
+
+impl<'a> ProgressEvent<'a> {
+    /// Classifies a segment of robocopy's output
+    pub fn classify(line: &'a str) -> Self {
+        match parse_progress_line(line) {
+            Some(percentage) => Self::Progress(percentage),
+            None => Self::Line(line)
+        }
+    }
+}
+
+/// Parses a progress segment such as `  7.3%` or `100%` into its percentage.
+/// 
+/// Returns `None` for any other line, such as file headers, or for percentages outside of 0 to 100.
+pub fn parse_progress_line(line: &str) -> Option<f32> {
+    line.trim()
+        .strip_suffix('%')
+        .and_then(|percentage| percentage.trim_end().parse::<f32>().ok())
+        .filter(|percentage| (0.0..=100.0).contains(percentage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_progress_segments() {
+        assert_eq!(parse_progress_line("  7.3%"), Some(7.3));
+        assert_eq!(parse_progress_line("100%"), Some(100.0));
+        assert_eq!(parse_progress_line("  0.0%\r"), Some(0.0));
+        assert_eq!(parse_progress_line(" 12.5 %"), Some(12.5));
+        assert_eq!(parse_progress_line("\t 50%\n"), Some(50.0));
+    }
+
+    #[test]
+    fn rejects_other_lines() {
+        assert_eq!(parse_progress_line("\t    New File  \t\t    4096\tC:\\source\\a.txt"), None);
+        assert_eq!(parse_progress_line("\t                   3\tC:\\source\\"), None);
+        assert_eq!(parse_progress_line("Files : *.*"), None);
+        assert_eq!(parse_progress_line("150%"), None);
+        assert_eq!(parse_progress_line("%"), None);
+        assert_eq!(parse_progress_line(""), None);
+    }
+
+    #[test]
+    fn classifies_events() {
+        assert_eq!(ProgressEvent::classify(" 25%"), ProgressEvent::Progress(25.0));
+        assert_eq!(ProgressEvent::classify("Options : *.*"), ProgressEvent::Line("Options : *.*"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{ops::Add, ffi::OsString};
+use std::{ops::{Add, Sub}, ffi::OsString};
 
 use crate::MultipleVariant;
 
@@ -6,7 +6,7 @@ use crate::MultipleVariant;
 /// 
 /// Default is both Data and Attributes
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FileProperties {
     DATA,
     ATTRIBUTES,
@@ -22,18 +22,20 @@ impl Add for FileProperties {
     
     #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Self) -> Self::Output {
-        let mut result_props = match self {
-            Self::_MULTIPLE(props) => props,
-            prop => {
-                let mut val = 2_u8.pow(prop.index_of().unwrap() as u32) + 2_u8; 
-                (0..6).map(|_| { val >>= 1; val == 1 }).collect::<Vec<bool>>().try_into().unwrap()
-            }
-        };
+        let mut result_props = self.flags();
+        result_props.iter_mut().zip(rhs.flags()).for_each(|(prop, added)| *prop |= added);
 
-        match rhs {
-            Self::_MULTIPLE(props) => result_props = result_props.iter().zip(props.iter()).map(|(a, b)| *a && *b).collect::<Vec<bool>>().try_into().unwrap(),
-            prop => result_props[prop.index_of().unwrap()] = true
-        }
+        Self::_MULTIPLE(result_props)
+    }
+}
+
+impl Sub for FileProperties {
+    type Output = Self;
+    
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: Self) -> Self::Output {
+        let mut result_props = self.flags();
+        result_props.iter_mut().zip(rhs.flags()).for_each(|(prop, removed)| *prop &= !removed);
 
         Self::_MULTIPLE(result_props)
     }
@@ -72,6 +74,10 @@ impl MultipleVariant for FileProperties {
             prop => vec![*prop],
         }
     }
+
+    fn iter_variants(&self) -> impl Iterator<Item = Self> {
+        Self::VARIANTS.into_iter().zip(self.flags()).filter_map(|(variant, exists)| exists.then_some(variant))
+    }
 }
 
 impl FileProperties {
@@ -96,6 +102,23 @@ impl FileProperties {
         }
     }
 
+    /// Returns the flags of the `_MULTIPLE` variant equivalent to this variant
+    fn flags(&self) -> [bool; 6] {
+        match self {
+            Self::_MULTIPLE(props) => *props,
+            prop => {
+                let mut props = [false; 6];
+                props[prop.index_of().unwrap()] = true;
+                props
+            }
+        }
+    }
+
+    /// Returns true if this variant contains `variant`, or all the variants of `variant` if it is a `_MULTIPLE`.
+    pub fn contains(&self, variant: Self) -> bool {
+        self.flags().iter().zip(variant.flags().iter()).all(|(exists, wanted)| *exists || !*wanted)
+    }
+
     /// Returns a variant containing all available file properties.
     #[allow(unused)]
     pub fn all() -> Self {
@@ -107,6 +130,27 @@ impl FileProperties {
     pub fn none() -> Self {
         Self::_MULTIPLE([false; 6])
     }
+
+    /// Returns the number of file properties in the variant.
+    pub fn count(&self) -> usize {
+        self.flags().iter().filter(|exists| **exists).count()
+    }
+
+    /// Returns true if the variant contains no file properties.
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Collapses a `_MULTIPLE` containing a single file property into its simple variant.
+    /// 
+    /// Empty variants and variants with several flags are returned as a `_MULTIPLE`, the empty one being equal to [none](Self::none).
+    pub fn canonicalize(self) -> Self {
+        let mut variants = self.iter_variants();
+        match (variants.next(), variants.next()) {
+            (Some(variant), None) => variant,
+            _ => Self::_MULTIPLE(self.flags())
+        }
+    }
 }
 
 
@@ -114,7 +158,7 @@ impl FileProperties {
 /// 
 /// Default is both Data and Attributes
 #[allow(non_camel_case_types)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DirectoryProperties {
     DATA,
     ATTRIBUTES,
@@ -127,18 +171,20 @@ impl Add for DirectoryProperties {
     
     #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: Self) -> Self::Output {
-        let mut result_props = match self {
-            Self::_MULTIPLE(props) => props,
-            prop => {
-                let mut val = 2_u8.pow(prop.index_of().unwrap() as u32) + 2_u8; 
-                (0..3).map(|_| { val >>= 1; val == 1 }).collect::<Vec<bool>>().try_into().unwrap()
-            }
-        };
+        let mut result_props = self.flags();
+        result_props.iter_mut().zip(rhs.flags()).for_each(|(prop, added)| *prop |= added);
 
-        match rhs {
-            Self::_MULTIPLE(props) => result_props = result_props.iter().zip(props.iter()).map(|(a, b)| *a && *b).collect::<Vec<bool>>().try_into().unwrap(),
-            prop => result_props[prop.index_of().unwrap()] = true
-        }
+        Self::_MULTIPLE(result_props)
+    }
+}
+
+impl Sub for DirectoryProperties {
+    type Output = Self;
+    
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: Self) -> Self::Output {
+        let mut result_props = self.flags();
+        result_props.iter_mut().zip(rhs.flags()).for_each(|(prop, removed)| *prop &= !removed);
 
         Self::_MULTIPLE(result_props)
     }
@@ -151,6 +197,8 @@ impl From<&DirectoryProperties> for OsString {
             DirectoryProperties::DATA => "/dcopy:D",
             DirectoryProperties::ATTRIBUTES => "/dcopy:A",
             DirectoryProperties::TIME_STAMPS => "/dcopy:T",
+            // Robocopy rejects an empty `/dcopy:`
+            DirectoryProperties::_MULTIPLE([false, false, false]) => "/nodcopy",
             DirectoryProperties::_MULTIPLE(props) => {
                 let part = ['D', 'A', 'T'].iter().zip(props.iter()).filter(|(_, exists)| **exists).unzip::<&char, &bool, String, Vec<bool>>().0;
                 full = String::from("/dcopy:") + part.as_str();
@@ -174,6 +222,10 @@ impl MultipleVariant for DirectoryProperties {
             prop => vec![*prop],
         }
     }
+
+    fn iter_variants(&self) -> impl Iterator<Item = Self> {
+        Self::VARIANTS.into_iter().zip(self.flags()).filter_map(|(variant, exists)| exists.then_some(variant))
+    }
 }
 
 impl DirectoryProperties {
@@ -192,6 +244,23 @@ impl DirectoryProperties {
         }
     }
 
+    /// Returns the flags of the `_MULTIPLE` variant equivalent to this variant
+    fn flags(&self) -> [bool; 3] {
+        match self {
+            Self::_MULTIPLE(props) => *props,
+            prop => {
+                let mut props = [false; 3];
+                props[prop.index_of().unwrap()] = true;
+                props
+            }
+        }
+    }
+
+    /// Returns true if this variant contains `variant`, or all the variants of `variant` if it is a `_MULTIPLE`.
+    pub fn contains(&self, variant: Self) -> bool {
+        self.flags().iter().zip(variant.flags().iter()).all(|(exists, wanted)| *exists || !*wanted)
+    }
+
     /// Returns a variant containing all available directory properties.
     #[allow(unused)]
     pub fn all() -> Self {
@@ -199,8 +268,141 @@ impl DirectoryProperties {
     }
 
     /// Returns a variant containing no directory properties.
+    /// 
+    /// Corresponds to `/nodcopy` option.
     #[allow(unused)]
     pub fn none() -> Self {
         Self::_MULTIPLE([false; 3])
     }
-}
\ No newline at end of file
+
+    /// Returns a variant containing data, attributes and time stamps, to keep the time stamps of directories
+    /// in addition to robocopy's default of data and attributes.
+    /// 
+    /// Corresponds to `/dcopy:DAT` option.
+    pub fn default_dat() -> Self {
+        Self::_MULTIPLE([true; 3])
+    }
+
+    /// Returns the number of directory properties in the variant.
+    pub fn count(&self) -> usize {
+        self.flags().iter().filter(|exists| **exists).count()
+    }
+
+    /// Returns true if the variant contains no directory properties.
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// Collapses a `_MULTIPLE` containing a single directory property into its simple variant.
+    /// 
+    /// Empty variants and variants with several flags are returned as a `_MULTIPLE`, the empty one being equal to [none](Self::none).
+    pub fn canonicalize(self) -> Self {
+        let mut variants = self.iter_variants();
+        match (variants.next(), variants.next()) {
+            (Some(variant), None) => variant,
+            _ => Self::_MULTIPLE(self.flags())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args<'a>(variants: impl IntoIterator<Item = &'a FileProperties>) -> Vec<OsString> {
+        variants.into_iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn iterates_every_variant_of_all() {
+        let variants: Vec<FileProperties> = FileProperties::all().iter_variants().collect();
+        assert_eq!(variants.len(), 6);
+        assert_eq!(args(&variants), args(&FileProperties::all().single_variants()));
+        assert_eq!(args(&variants), args(&FileProperties::VARIANTS));
+
+        assert_eq!(args(&FileProperties::DATA.iter_variants().collect::<Vec<_>>()), args(&[FileProperties::DATA]));
+        assert_eq!(FileProperties::none().iter_variants().count(), 0);
+        assert_eq!(DirectoryProperties::all().iter_variants().count(), 3);
+    }
+
+    #[test]
+    fn counts_flags() {
+        assert_eq!(FileProperties::all().count(), 6);
+        assert_eq!(FileProperties::DATA.count(), 1);
+        assert_eq!((FileProperties::DATA + FileProperties::ATTRIBUTES).count(), 2);
+        assert_eq!(FileProperties::none().count(), 0);
+        assert!(FileProperties::none().is_empty());
+        assert!(!FileProperties::DATA.is_empty());
+
+        assert_eq!(DirectoryProperties::all().count(), 3);
+        assert!(DirectoryProperties::none().is_empty());
+        assert!(!DirectoryProperties::TIME_STAMPS.is_empty());
+    }
+
+    #[test]
+    fn contains_present_and_absent_variants() {
+        let properties = FileProperties::DATA + FileProperties::OWNER_INFO;
+        assert!(properties.contains(FileProperties::DATA));
+        assert!(properties.contains(FileProperties::OWNER_INFO));
+        assert!(!properties.contains(FileProperties::AUDITING_INFO));
+        assert!(properties.contains(FileProperties::DATA + FileProperties::OWNER_INFO));
+        assert!(!properties.contains(FileProperties::DATA + FileProperties::TIME_STAMPS));
+        assert!(FileProperties::DATA.contains(FileProperties::none()));
+
+        assert!(DirectoryProperties::all().contains(DirectoryProperties::TIME_STAMPS));
+        assert!(!DirectoryProperties::DATA.contains(DirectoryProperties::ATTRIBUTES));
+    }
+
+    #[test]
+    fn all_minus_auditing_info() {
+        let properties = FileProperties::all() - FileProperties::AUDITING_INFO;
+        assert_eq!(properties.count(), 5);
+        assert!(!properties.contains(FileProperties::AUDITING_INFO));
+        assert_eq!(OsString::from(properties), "/copy:DATSO");
+
+        assert!((FileProperties::DATA - FileProperties::DATA).is_empty());
+        assert_eq!(OsString::from(DirectoryProperties::all() - DirectoryProperties::TIME_STAMPS), "/dcopy:DA");
+    }
+
+    #[test]
+    fn add_unions_flags() {
+        // Adding single variants used to set the wrong flag
+        assert_eq!(OsString::from(FileProperties::DATA + FileProperties::TIME_STAMPS), "/copy:DT");
+        assert_eq!(OsString::from(FileProperties::AUDITING_INFO + FileProperties::DATA), "/copy:DU");
+        // Adding two _MULTIPLE variants used to intersect them
+        let union = (FileProperties::DATA + FileProperties::ATTRIBUTES) + (FileProperties::TIME_STAMPS + FileProperties::OWNER_INFO);
+        assert_eq!(OsString::from(union), "/copy:DATO");
+        assert_eq!(OsString::from(FileProperties::all() + FileProperties::none()), OsString::from(FileProperties::all()));
+        assert_eq!(OsString::from(DirectoryProperties::DATA + DirectoryProperties::TIME_STAMPS), "/dcopy:DT");
+    }
+
+    #[test]
+    fn all_equals_every_flag_set() {
+        assert_eq!(FileProperties::all(), FileProperties::_MULTIPLE([true; 6]));
+        assert_ne!(FileProperties::all(), FileProperties::none());
+    }
+
+    #[test]
+    fn canonicalize_collapses_single_flag() {
+        assert_eq!(FileProperties::_MULTIPLE([true, false, false, false, false, false]).canonicalize(), FileProperties::DATA);
+        assert_eq!((FileProperties::all() - FileProperties::all() + FileProperties::TIME_STAMPS).canonicalize(), FileProperties::TIME_STAMPS);
+        assert_eq!(DirectoryProperties::_MULTIPLE([false, true, false]).canonicalize(), DirectoryProperties::ATTRIBUTES);
+    }
+
+    #[test]
+    fn canonicalize_keeps_empty_and_several_flags() {
+        assert_eq!(FileProperties::_MULTIPLE([false; 6]).canonicalize(), FileProperties::none());
+        assert_eq!(DirectoryProperties::_MULTIPLE([false; 3]).canonicalize(), DirectoryProperties::none());
+
+        let several = FileProperties::DATA + FileProperties::ATTRIBUTES;
+        assert_eq!(several.canonicalize(), several);
+        assert_eq!(FileProperties::DATA.canonicalize(), FileProperties::DATA);
+    }
+
+    #[test]
+    fn directory_properties_arguments() {
+        assert_eq!(OsString::from(DirectoryProperties::none()), "/nodcopy");
+        assert_eq!(OsString::from(DirectoryProperties::default_dat()), "/dcopy:DAT");
+        assert_eq!(OsString::from(DirectoryProperties::DATA + DirectoryProperties::TIME_STAMPS), "/dcopy:DT");
+    }
+}
@@ -0,0 +1,571 @@
+//! Parsing of the job summary printed by robocopy
+//!
+//! The summary is the table printed at the end of a run (unless `/njs` is used):
+//!
+//! ```text
+//!                Total    Copied   Skipped  Mismatch    FAILED    Extras
+//!     Dirs :         1         0         1         0         0         0
+//!    Files :         3         3         0         0         0         0
+//!    Bytes :    1.23 m    1.23 m         0         0         0         0
+//!    Times :   0:00:00   0:00:00                       0:00:00   0:00:00
+//! ```
+//!
+//! Rows are read by position rather than by label so that localized outputs can be parsed too.
+
+use std::{path::{Path, PathBuf}, time::Duration};
+
+use crate::{Error, exit_codes::OkExitCode};
+
+/// The outcome of a successful copy
+#[derive(Debug)]
+pub struct CopyResult {
+    /// The exit code of robocopy
+    pub code: OkExitCode,
+    /// The number of bytes copied
+    pub bytes_copied: u64,
+    /// The number of files copied
+    pub files_copied: u64,
+    /// The duration of the copy
+    pub duration: Duration,
+    /// The full job summary
+    pub report: RobocopyReport,
+    /// Whether files were copied in backup mode, always with `/b`, and with `/zb` when access was denied in restartable mode
+    pub used_backup_mode: bool,
+}
+
+/// The classification of a file or directory in verbose output (`/v`)
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileClass {
+    /// The file doesn't exist in the destination (`New File`)
+    NEW_FILE,
+    /// The source file is newer than the destination file (`Newer`)
+    NEWER,
+    /// The source file is older than the destination file (`Older`)
+    OLDER,
+    /// The files have the same time stamp but different sizes (`Changed`)
+    CHANGED,
+    /// The files only differ by their attributes (`Tweaked`)
+    TWEAKED,
+    /// The files only differ by their change time (`Modified`)
+    MODIFIED,
+    /// The files are identical (`same`)
+    SAME,
+    /// The file only exists in the destination (`*EXTRA File`)
+    EXTRA_FILE,
+    /// The file only exists in the source (`Lonely`)
+    LONELY,
+    /// A file in the source matches a directory in the destination or vice versa (`*Mismatch`)
+    MISMATCH,
+    /// The file is excluded by a filter (`attrib`, `named`, `large`, `small`, `too old`, `too new` or `jct`)
+    EXCLUDED,
+    /// The directory doesn't exist in the destination (`New Dir`)
+    NEW_DIR,
+    /// The directory only exists in the destination (`*EXTRA Dir`)
+    EXTRA_DIR,
+}
+
+impl FileClass {
+    fn parse(label: &str) -> Option<Self> {
+        Some(match label {
+            "New File" => Self::NEW_FILE,
+            "Newer" => Self::NEWER,
+            "Older" => Self::OLDER,
+            "Changed" => Self::CHANGED,
+            "Tweaked" => Self::TWEAKED,
+            "Modified" => Self::MODIFIED,
+            "same" => Self::SAME,
+            "*EXTRA File" => Self::EXTRA_FILE,
+            "Lonely" => Self::LONELY,
+            "*Mismatch" => Self::MISMATCH,
+            "attrib" | "named" | "large" | "small" | "too old" | "too new" | "jct" => Self::EXCLUDED,
+            "New Dir" => Self::NEW_DIR,
+            "*EXTRA Dir" => Self::EXTRA_DIR,
+            _ => return None
+        })
+    }
+
+    /// Returns true if the class describes a directory
+    pub fn is_dir(&self) -> bool {
+        matches!(self, Self::NEW_DIR | Self::EXTRA_DIR)
+    }
+}
+
+/// A file or directory listed in verbose output (`/v`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRecord {
+    /// The classification of the file
+    pub class: FileClass,
+    /// The size of the file in bytes, `None` for directories
+    pub size: Option<u64>,
+    /// The path of the file, absolute when full path names (`/fp`) are enabled
+    pub path: PathBuf,
+}
+
+impl FileRecord {
+    /// Parses the file records from the standard output of robocopy.
+    /// 
+    /// Lines which aren't file records, such as directory headers, are ignored.
+    pub fn parse_all(output: &str) -> Vec<Self> {
+        output.lines().filter_map(Self::parse).collect()
+    }
+
+    /// Parses a single line of verbose output
+    pub fn parse(line: &str) -> Option<Self> {
+        // Progress is printed after the path and redrawn with carriage returns
+        let line = line.split('\r').next()?;
+        let fields: Vec<&str> = line.split('\t').map(str::trim).filter(|field| !field.is_empty()).collect();
+
+        match fields[..] {
+            [label, .., path] if fields.len() >= 2 => {
+                // Directory labels are followed by the number of files, separated by spaces
+                let class = FileClass::parse(label).or_else(|| match label.rsplit_once(' ') {
+                    Some((label, count)) if count.parse::<i64>().is_ok() => FileClass::parse(label.trim_end()),
+                    _ => None
+                })?;
+                let size = match fields[1..fields.len() - 1] {
+                    [size] if !class.is_dir() => parse_size(size),
+                    _ => None
+                };
+                Some(FileRecord { class, size, path: Path::new(path).to_path_buf() })
+            },
+            _ => None
+        }
+    }
+}
+
+/// The changes a command would make, see [RobocopyCommand::diff](crate::RobocopyCommand::diff)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncDiff {
+    /// The source files missing from the destination
+    pub new: Vec<PathBuf>,
+    /// The source files which differ from the destination, such as newer or older ones
+    pub modified: Vec<PathBuf>,
+    /// The destination files missing from the source
+    pub extra: Vec<PathBuf>,
+    /// The number of bytes of the new and modified files
+    pub total_bytes: u64,
+}
+
+/// What a destructive command would do, see [RobocopyCommand::execute_with_confirm](crate::RobocopyCommand::execute_with_confirm)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DestructiveSummary {
+    /// The number of destination files and directories that would be deleted because they aren't in the source
+    pub deletions: usize,
+    /// The number of source files that would be moved
+    pub moves: usize,
+}
+
+/// The amount of work a command would do, see [RobocopyCommand::estimate](crate::RobocopyCommand::estimate)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Estimate {
+    /// The number of files that would be copied
+    pub total_files: u64,
+    /// The number of bytes that would be copied
+    pub total_bytes: u64,
+}
+
+/// A row of the job summary (`Dirs`, `Files` or `Bytes`)
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SummaryRow {
+    pub total: u64,
+    pub copied: u64,
+    pub skipped: u64,
+    pub mismatch: u64,
+    pub failed: u64,
+    pub extras: u64,
+}
+
+/// The `Times` row of the job summary
+/// 
+/// With the `serde` feature, durations are serialized as whole seconds.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TimesRow {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_secs"))]
+    pub total: Duration,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_secs"))]
+    pub copied: Duration,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_secs"))]
+    pub failed: Duration,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_secs"))]
+    pub extras: Duration,
+}
+
+#[cfg(feature = "serde")]
+fn serialize_secs<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+/// The job summary of a robocopy run
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RobocopyReport {
+    /// Directories statistics
+    pub dirs: SummaryRow,
+    /// Files statistics
+    pub files: SummaryRow,
+    /// Bytes statistics. Sizes printed with a `k`, `m`, `g` or `t` suffix are converted to bytes,
+    /// use `/bytes` for exact values.
+    pub bytes: SummaryRow,
+    /// Times statistics, if robocopy printed them
+    pub times: Option<TimesRow>,
+}
+
+impl RobocopyReport {
+    /// Parses the job summary from the standard output of robocopy.
+    ///
+    /// The last summary table found in `output` is used.
+    pub fn parse(output: &str) -> Result<Self, Error> {
+        let table_start = output.lines()
+            .enumerate()
+            .filter(|(_, line)| is_separator(line))
+            .map(|(index, _)| index + 1)
+            .last()
+            .ok_or_else(|| parse_error(String::from("no job summary found"), None))?;
+
+        let mut rows = output.lines()
+            .skip(table_start)
+            .filter_map(|line| line.split_once(':').map(|(_, values)| (line, values)));
+
+        let mut next_row = |name: &str| rows.next().ok_or_else(|| parse_error(format!("missing {} row in job summary", name), None));
+
+        let (line, values) = next_row("dirs")?;
+        let dirs = parse_row(line, values)?;
+        let (line, values) = next_row("files")?;
+        let files = parse_row(line, values)?;
+        let (line, values) = next_row("bytes")?;
+        let bytes = parse_row(line, values)?;
+        let times = match next_row("times") {
+            Ok((line, values)) if values.contains(':') => Some(parse_times_row(line, values)?),
+            _ => None
+        };
+
+        Ok(RobocopyReport { dirs, files, bytes, times })
+    }
+
+    /// Header matching the columns of [to_csv_row](RobocopyReport::to_csv_row).
+    pub const CSV_HEADER: &'static str = "dirs_total,dirs_copied,dirs_skipped,dirs_mismatch,dirs_failed,dirs_extras,\
+files_total,files_copied,files_skipped,files_mismatch,files_failed,files_extras,\
+bytes_total,bytes_copied,bytes_skipped,bytes_mismatch,bytes_failed,bytes_extras,\
+times_total,times_copied,times_failed,times_extras";
+
+    /// Serializes the report as a JSON object, with a `dirs`, `files`, `bytes` and `times` member.
+    /// 
+    /// Times are expressed in seconds, `times` is `null` if robocopy didn't print them.
+    /// 
+    /// This requires the `serde` feature.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("a report only contains numbers")
+    }
+
+    /// Serializes the report as a single CSV line, without line ending, whose columns are described by [CSV_HEADER](RobocopyReport::CSV_HEADER).
+    /// 
+    /// Times are expressed in seconds and left empty if robocopy didn't print them.
+    pub fn to_csv_row(&self) -> String {
+        let times = match &self.times {
+            Some(times) => [times.total, times.copied, times.failed, times.extras].iter().map(|time| time.as_secs().to_string()).collect::<Vec<String>>().join(","),
+            None => String::from(",,,")
+        };
+
+        format!("{},{},{},{}", self.dirs.to_csv(), self.files.to_csv(), self.bytes.to_csv(), times)
+    }
+}
+
+impl SummaryRow {
+    fn values(&self) -> [u64; 6] {
+        [self.total, self.copied, self.skipped, self.mismatch, self.failed, self.extras]
+    }
+
+    fn to_csv(self) -> String {
+        self.values().iter().map(u64::to_string).collect::<Vec<String>>().join(",")
+    }
+}
+
+/// Returns the path of the first file or directory robocopy was denied access to.
+/// 
+/// Robocopy reports it as `ERROR 5 (0x00000005) Accessing Source Directory C:\source\`.
+pub(crate) fn access_denied_path(output: &str) -> Option<PathBuf> {
+    output.lines()
+        .filter_map(|line| line.split_once("ERROR 5 (0x00000005)").map(|(_, rest)| rest))
+        .find_map(extract_path)
+        .map(PathBuf::from)
+}
+
+/// Returns true if robocopy fell back to backup mode (`/zb`).
+/// 
+/// The fallback happens when access is denied in restartable mode, reported as `ERROR 5 (0x00000005)`,
+/// or is mentioned by a line about backup mode other than the options of the job header.
+pub(crate) fn used_backup_fallback(output: &str) -> bool {
+    output.lines().any(|line| {
+        let line = line.trim_start();
+        line.contains("ERROR 5 (0x00000005)")
+            || (!line.starts_with("Options") && line.to_lowercase().contains("backup mode"))
+    })
+}
+
+/// Extracts the absolute path (`C:\...` or `\\server\...`) ending a line
+fn extract_path(text: &str) -> Option<&str> {
+    let unc = text.find("\\\\");
+    let drive = text.find(":\\")
+        .filter(|&index| index > 0 && text.as_bytes()[index - 1].is_ascii_alphabetic())
+        .map(|index| index - 1);
+
+    let start = match (unc, drive) {
+        (Some(unc), Some(drive)) => unc.min(drive),
+        (unc, drive) => unc.or(drive)?
+    };
+    Some(text[start..].trim_end())
+}
+
+fn is_separator(line: &str) -> bool {
+    let line = line.trim();
+    line.len() > 10 && line.chars().all(|c| c == '-')
+}
+
+fn parse_error(context: String, source: Option<Box<dyn std::error::Error + Send + Sync>>) -> Error {
+    Error::ReportParse { context, source }
+}
+
+/// Returns the multiplier of a size suffix
+fn size_multiplier(token: &str) -> Option<f64> {
+    match token {
+        "k" | "K" => Some(1024_f64),
+        "m" | "M" => Some(1024_f64.powi(2)),
+        "g" | "G" => Some(1024_f64.powi(3)),
+        "t" | "T" => Some(1024_f64.powi(4)),
+        _ => None
+    }
+}
+
+/// Parses a size such as `123` or `1.2 m`
+fn parse_size(size: &str) -> Option<u64> {
+    let mut tokens = size.split_whitespace();
+    let value = tokens.next()?.replace(',', ".").parse::<f64>().ok()?;
+    let multiplier = match tokens.next() {
+        Some(suffix) => size_multiplier(suffix)?,
+        None => 1_f64
+    };
+    Some((value * multiplier).round() as u64)
+}
+
+fn parse_row(line: &str, values: &str) -> Result<SummaryRow, Error> {
+    let mut numbers: Vec<u64> = Vec::new();
+    let mut last: Option<f64> = None;
+
+    for token in values.split_whitespace() {
+        match (size_multiplier(token), last.take()) {
+            (Some(multiplier), Some(value)) => numbers.push((value * multiplier).round() as u64),
+            (Some(_), None) => return Err(parse_error(format!("unexpected size suffix in summary line {:?}", line), None)),
+            (None, previous) => {
+                if let Some(value) = previous {
+                    numbers.push(value.round() as u64);
+                }
+                last = Some(token.replace(',', ".").parse::<f64>()
+                    .map_err(|err| parse_error(format!("invalid value {:?} in summary line {:?}", token, line), Some(Box::new(err))))?);
+            }
+        }
+    }
+    if let Some(value) = last {
+        numbers.push(value.round() as u64);
+    }
+
+    match numbers[..] {
+        [total, copied, skipped, mismatch, failed, extras] => Ok(SummaryRow { total, copied, skipped, mismatch, failed, extras }),
+        _ => Err(parse_error(format!("expected 6 values, found {} in summary line {:?}", numbers.len(), line), None))
+    }
+}
+
+fn parse_times_row(line: &str, values: &str) -> Result<TimesRow, Error> {
+    let durations = values.split_whitespace()
+        .map(|token| parse_duration(token).ok_or_else(|| parse_error(format!("invalid time {:?} in summary line {:?}", token, line), None)))
+        .collect::<Result<Vec<Duration>, Error>>()?;
+
+    match durations[..] {
+        [total, copied, failed, extras] => Ok(TimesRow { total, copied, failed, extras }),
+        _ => Err(parse_error(format!("expected 4 times, found {} in summary line {:?}", durations.len(), line), None))
+    }
+}
+
+/// Parses a `h:mm:ss` duration
+fn parse_duration(token: &str) -> Option<Duration> {
+    token.split(':')
+        .try_fold(0_u64, |seconds, part| part.parse::<u64>().ok().map(|n| seconds * 60 + n))
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMMARY: &str = "\
+------------------------------------------------------------------------------
+
+               Total    Copied   Skipped  Mismatch    FAILED    Extras
+    Dirs :         2         1         1         0         0         0
+   Files :         3         2         1         0         0         1
+   Bytes :     1.5 k       512      1024         0         0         0
+   Times :   0:00:05   0:00:04                       0:00:00   0:00:01
+";
+
+    #[test]
+    fn parses_summary() {
+        let report = RobocopyReport::parse(SUMMARY).unwrap();
+
+        assert_eq!((report.dirs.total, report.dirs.copied, report.dirs.skipped), (2, 1, 1));
+        assert_eq!((report.files.total, report.files.copied, report.files.extras), (3, 2, 1));
+        assert_eq!((report.bytes.total, report.bytes.copied, report.bytes.skipped), (1536, 512, 1024));
+        assert_eq!(report.times.unwrap().total, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn malformed_summary_reports_offending_line() {
+        let output = SUMMARY.replace("   Files :         3         2", "   Files :         3       two");
+
+        match RobocopyReport::parse(&output) {
+            Err(Error::ReportParse { context, source }) => {
+                assert!(context.contains("Files :         3       two"), "{}", context);
+                assert!(source.is_some());
+            },
+            result => panic!("expected a parse error, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn missing_summary_is_an_error() {
+        assert!(matches!(RobocopyReport::parse("no summary here"), Err(Error::ReportParse { .. })));
+    }
+
+    const ACCESS_DENIED: &str = "\
+2024/03/15 10:00:00 ERROR 5 (0x00000005) Accessing Source Directory C:\\source\\private\\
+Access is denied.
+";
+
+    #[test]
+    fn finds_access_denied_path() {
+        assert_eq!(access_denied_path(ACCESS_DENIED), Some(PathBuf::from("C:\\source\\private\\")));
+        assert_eq!(access_denied_path(SUMMARY), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_has_one_object_per_row() {
+        let json: serde_json::Value = serde_json::from_str(&RobocopyReport::parse(SUMMARY).unwrap().to_json()).unwrap();
+
+        assert_eq!(json["dirs"], serde_json::json!({ "total": 2, "copied": 1, "skipped": 1, "mismatch": 0, "failed": 0, "extras": 0 }));
+        assert_eq!(json["files"]["extras"], 1);
+        assert_eq!(json["bytes"]["total"], 1536);
+        assert_eq!(json["times"], serde_json::json!({ "total": 5, "copied": 4, "failed": 0, "extras": 1 }));
+
+        let without_times = RobocopyReport { times: None, ..RobocopyReport::default() };
+        assert!(serde_json::from_str::<serde_json::Value>(&without_times.to_json()).unwrap()["times"].is_null());
+    }
+
+    #[test]
+    fn csv_row_follows_header_columns() {
+        let report = RobocopyReport::parse(SUMMARY).unwrap();
+        let header: Vec<&str> = RobocopyReport::CSV_HEADER.split(',').collect();
+        let row = report.to_csv_row();
+        let values: Vec<&str> = row.split(',').collect();
+
+        assert_eq!(header.len(), 22);
+        assert_eq!(values.len(), header.len());
+        let column = |name: &str| values[header.iter().position(|column| *column == name).unwrap()];
+        assert_eq!(column("dirs_total"), "2");
+        assert_eq!(column("files_copied"), "2");
+        assert_eq!(column("files_extras"), "1");
+        assert_eq!(column("bytes_skipped"), "1024");
+        assert_eq!(column("times_total"), "5");
+        assert_eq!(column("times_extras"), "1");
+
+        let without_times = RobocopyReport { times: None, ..report }.to_csv_row();
+        assert!(without_times.ends_with(",,,,"));
+        assert_eq!(without_times.split(',').count(), header.len());
+    }
+
+    #[test]
+    fn parses_bytes_copied() {
+        let exact = SUMMARY.replace("   Bytes :     1.5 k       512      1024", "   Bytes :   1234567   1233543      1024");
+        assert_eq!(RobocopyReport::parse(&exact).unwrap().bytes.copied, 1_233_543);
+
+        let suffixed = SUMMARY.replace("   Bytes :     1.5 k       512      1024", "   Bytes :    2.50 m    1.25 m      1024");
+        assert_eq!(RobocopyReport::parse(&suffixed).unwrap().bytes.copied, 1_310_720);
+    }
+
+    const VERBOSE: &str = "\
+\t                   3\tC:\\source\\
+\t    New File  \t\t    4096\tC:\\source\\new.txt
+\t    Newer     \t\t     512\tC:\\source\\newer.txt
+\t    Older     \t\t     256\tC:\\source\\older.txt
+\t      same    \t\t    1024\tC:\\source\\same.txt
+\t    Tweaked   \t\t      64\tC:\\source\\tweaked.txt
+\t    named     \t\t      32\tC:\\source\\skip.tmp
+\t*EXTRA File  \t\t     128\tC:\\destination\\extra.txt
+\t  New Dir          2\tC:\\source\\sub\\
+\t*EXTRA Dir        -1\tC:\\destination\\old\\
+  50%\r100%\r
+";
+
+    #[test]
+    fn parses_records_of_several_classes() {
+        let records = FileRecord::parse_all(VERBOSE);
+        let classes: Vec<(FileClass, Option<u64>)> = records.iter().map(|record| (record.class, record.size)).collect();
+
+        assert_eq!(classes, [
+            (FileClass::NEW_FILE, Some(4096)),
+            (FileClass::NEWER, Some(512)),
+            (FileClass::OLDER, Some(256)),
+            (FileClass::SAME, Some(1024)),
+            (FileClass::TWEAKED, Some(64)),
+            (FileClass::EXCLUDED, Some(32)),
+            (FileClass::EXTRA_FILE, Some(128)),
+            (FileClass::NEW_DIR, None),
+            (FileClass::EXTRA_DIR, None),
+        ]);
+        assert_eq!(records[0].path, PathBuf::from("C:\\source\\new.txt"));
+        assert_eq!(records[8].path, PathBuf::from("C:\\destination\\old\\"));
+    }
+
+    const SUFFIXED_SUMMARY: &str = "\
+------------------------------------------------------------------------------
+
+               Total    Copied   Skipped  Mismatch    FAILED    Extras
+    Dirs :         3         2         1         0         0         0
+   Files :        12        10         2         0         0         0
+   Bytes :    1.50 g   512.0 m     2.5 k         0         0       100
+";
+
+    #[test]
+    fn parses_suffixed_sizes() {
+        let report = RobocopyReport::parse(SUFFIXED_SUMMARY).unwrap();
+
+        assert_eq!(report.files.total, 12);
+        assert_eq!(report.files.copied, 10);
+        assert_eq!(report.bytes.total, 1_610_612_736);
+        assert_eq!(report.bytes.copied, 536_870_912);
+        assert_eq!(report.bytes.skipped, 2_560);
+        assert_eq!(report.bytes.extras, 100);
+    }
+
+    const BACKUP_FALLBACK: &str = "\
+  Options : *.* /S /E /DCOPY:DA /COPY:DAT /ZB /R:1000000 /W:30
+
+------------------------------------------------------------------------------
+
+\t                   1\tC:\\source\\
+2024/03/15 10:00:00 ERROR 5 (0x00000005) Copying File C:\\source\\locked.db
+Access is denied.
+Restarting in Backup mode...
+\t    New File  \t\t    4096\tC:\\source\\locked.db
+";
+
+    #[test]
+    fn detects_backup_fallback() {
+        assert!(used_backup_fallback(BACKUP_FALLBACK));
+        assert!(used_backup_fallback("Copying in Backup Mode"));
+        assert!(!used_backup_fallback("  Options : *.* /S /E /DCOPY:DA /COPY:DAT /ZB /R:1000000 /W:30\n"));
+        assert!(!used_backup_fallback(SUMMARY));
+    }
+}
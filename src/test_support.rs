@@ -0,0 +1,147 @@
+//! Helpers for tests running a fake robocopy that prints canned output
+
+use std::{fs, path::{Path, PathBuf}, process, sync::atomic::{AtomicUsize, Ordering}};
+
+/// A temporary directory, removed when dropped
+pub(crate) struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!("robocopyrs-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes a file relative to the directory, creating its parents
+    pub(crate) fn write(&self, relative: &str, content: &str) -> PathBuf {
+        let path = self.path.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A script standing in for robocopy, which records its arguments, prints `output` and exits with `exit_code`.
+///
+/// Dry runs (`/l`) print `dry_run_output` instead, which defaults to `output`.
+pub(crate) struct FakeRobocopy {
+    dir: TempDir,
+    script: PathBuf,
+}
+
+impl FakeRobocopy {
+    pub(crate) fn new(output: &str, exit_code: i32) -> Self {
+        Self::with_dry_run(output, output, exit_code)
+    }
+
+    pub(crate) fn with_dry_run(dry_run_output: &str, output: &str, exit_code: i32) -> Self {
+        let dir = TempDir::new();
+        let dry_run = dir.write("dry_run_output", dry_run_output);
+        let real = dir.write("output", output);
+        let calls = dir.path().join("calls");
+
+        #[cfg(unix)]
+        let script = {
+            use std::os::unix::fs::PermissionsExt;
+
+            let script = dir.write("robocopy", &format!(
+                "#!/bin/sh\necho \"$*\" >> '{}'\ncase \" $* \" in\n  *\" /l \"*) cat '{}' ;;\n  *) cat '{}' ;;\nesac\nexit {}\n",
+                calls.display(), dry_run.display(), real.display(), exit_code
+            ));
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+            script
+        };
+        #[cfg(windows)]
+        let script = dir.write("robocopy.cmd", &format!(
+            "@echo off\r\necho %*>> \"{}\"\r\necho  %* | findstr /i /c:\" /l\" >nul && (type \"{}\") || (type \"{}\")\r\nexit /b {}\r\n",
+            calls.display(), dry_run.display(), real.display(), exit_code
+        ));
+
+        FakeRobocopy { dir, script }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.script
+    }
+
+    /// Returns the arguments of each call, separated by spaces
+    pub(crate) fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.path().join("calls"))
+            .map(|calls| calls.lines().map(|line| line.trim().to_owned()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A script standing in for `net`, which records its arguments.
+/// 
+/// `net use` lists the `connections`, connecting with credentials fails by printing `connect_error` if it's set,
+/// and other calls succeed, like connecting to a share with the current user's credentials does.
+pub(crate) struct FakeNet {
+    dir: TempDir,
+    script: PathBuf,
+}
+
+impl FakeNet {
+    pub(crate) fn new(connections: &[&str], connect_error: Option<&str>) -> Self {
+        let dir = TempDir::new();
+        let calls = dir.path().join("calls");
+        let listing = dir.write("listing", &format!(
+            "Status       Local     Remote                    Network\n\n{}The command completed successfully.\n",
+            connections.iter().map(|share| format!("OK                     {:<25} Microsoft Windows Network\n", share)).collect::<String>()
+        ));
+
+        #[cfg(unix)]
+        let script = {
+            use std::os::unix::fs::PermissionsExt;
+
+            let connect = match connect_error {
+                Some(error) => format!("echo '{}' >&2; exit 2", error),
+                None => String::from("exit 0"),
+            };
+            let script = dir.write("net", &format!(
+                "#!/bin/sh\nprintf '%s\\n' \"$*\" >> '{}'\ncase \"$*\" in\n  use) cat '{}' ;;\n  *\"/user:\"*) {} ;;\nesac\n",
+                calls.display(), listing.display(), connect
+            ));
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+            script
+        };
+        #[cfg(windows)]
+        let script = {
+            let connect = match connect_error {
+                Some(error) => format!("(echo {}>&2 & exit /b 2)", error),
+                None => String::from("exit /b 0"),
+            };
+            dir.write("net.cmd", &format!(
+                "@echo off\r\necho %*>> \"{}\"\r\nif \"%*\"==\"use\" (type \"{}\" & exit /b 0)\r\necho %* | findstr /c:\"/user:\" >nul && {}\r\nexit /b 0\r\n",
+                calls.display(), listing.display(), connect
+            ))
+        };
+
+        FakeNet { dir, script }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.script
+    }
+
+    /// Returns the arguments of each call, separated by spaces
+    pub(crate) fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.dir.path().join("calls"))
+            .map(|calls| calls.lines().map(|line| line.trim().to_owned()).collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,169 @@
+//! Connection to remote shares requiring credentials
+//!
+//! The connection is established with `net use` before running robocopy and deleted afterwards,
+//! unless the share was already connected.
+
+use std::{ffi::{OsStr, OsString}, fmt::Debug, path::{Path, PathBuf}, process::Command};
+
+use crate::Error;
+
+/// Credentials used to connect to a UNC share (`\\server\share`)
+#[derive(Clone, PartialEq, Eq)]
+pub struct UncCredentials {
+    /// The share to connect to, such as `\\server\share`
+    pub share: PathBuf,
+    /// The user name, such as `DOMAIN\user`
+    pub username: String,
+    /// The password of the user.
+    /// 
+    /// It's passed to `net use` as an argument, so it can be seen in the process list while connecting.
+    pub password: String,
+}
+
+impl Debug for UncCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UncCredentials")
+            .field("share", &self.share)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// A connection to a share, deleted when dropped if it was created by [connect](UncConnection::connect)
+pub(crate) struct UncConnection {
+    net: OsString,
+    share: PathBuf,
+    created: bool,
+}
+
+impl UncConnection {
+    /// Connects to the share with `net use`, unless a connection to it is already listed by `net use`
+    pub(crate) fn connect(credentials: &UncCredentials) -> Result<Self, Error> {
+        Self::connect_with(OsStr::new("net"), credentials)
+    }
+
+    /// Connects to the share running `net` as the `net` command
+    fn connect_with(net: &OsStr, credentials: &UncCredentials) -> Result<Self, Error> {
+        let mut connection = UncConnection { net: net.to_owned(), share: credentials.share.clone(), created: false };
+
+        // An existing connection is kept as is and not deleted afterwards
+        let connections = Command::new(net).arg("use").output()?;
+        if !connections.status.success() {
+            return Err(Error::UncConnection {
+                share: credentials.share.clone(),
+                message: String::from_utf8_lossy(&connections.stderr).trim().to_owned(),
+            });
+        }
+        if is_listed(&String::from_utf8_lossy(&connections.stdout), &credentials.share) {
+            return Ok(connection);
+        }
+
+        let output = Command::new(net)
+            .arg("use")
+            .arg(&credentials.share)
+            .arg(&credentials.password)
+            .arg(format!("/user:{}", credentials.username))
+            .arg("/persistent:no")
+            .output()?;
+
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+            return Err(if message.contains("1219") {
+                Error::UncConflictingCredentials { share: credentials.share.clone() }
+            } else {
+                Error::UncConnection { share: credentials.share.clone(), message }
+            });
+        }
+
+        connection.created = true;
+        Ok(connection)
+    }
+}
+
+/// Returns true if the share is one of the remote names listed by `net use`, ignoring the case like Windows does
+fn is_listed(connections: &str, share: &Path) -> bool {
+    let share = share.to_string_lossy();
+    connections.lines().any(|line| line.split_whitespace().any(|name| name.eq_ignore_ascii_case(&share)))
+}
+
+impl Drop for UncConnection {
+    /// Deletes the connection it created, even if the copy failed
+    fn drop(&mut self) {
+        if self.created {
+            let _ = Command::new(&self.net).arg("use").arg(&self.share).args(["/delete", "/y"]).output();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeNet;
+
+    fn credentials() -> UncCredentials {
+        UncCredentials { share: PathBuf::from("\\\\server\\share"), username: String::from("DOMAIN\\user"), password: String::from("secret") }
+    }
+
+    #[test]
+    fn connects_then_deletes_the_connection() {
+        let net = FakeNet::new(&[], None);
+
+        let connection = UncConnection::connect_with(net.path().as_os_str(), &credentials()).unwrap();
+        assert_eq!(net.calls().len(), 2);
+        drop(connection);
+
+        assert_eq!(net.calls(), [
+            "use",
+            "use \\\\server\\share secret /user:DOMAIN\\user /persistent:no",
+            "use \\\\server\\share /delete /y",
+        ]);
+    }
+
+    #[test]
+    fn keeps_an_existing_connection() {
+        let net = FakeNet::new(&["\\\\Server\\share"], None);
+
+        drop(UncConnection::connect_with(net.path().as_os_str(), &credentials()).unwrap());
+
+        assert_eq!(net.calls(), ["use"]);
+    }
+
+    #[test]
+    fn reports_conflicting_credentials() {
+        let net = FakeNet::new(&[], Some("System error 1219 has occurred."));
+
+        match UncConnection::connect_with(net.path().as_os_str(), &credentials()) {
+            Err(Error::UncConflictingCredentials { share }) => assert_eq!(share, PathBuf::from("\\\\server\\share")),
+            result => panic!("expected conflicting credentials, got {:?}", result.map(|_| ()))
+        }
+        assert_eq!(net.calls().len(), 2);
+    }
+
+    #[test]
+    fn reports_other_connection_errors() {
+        let net = FakeNet::new(&[], Some("System error 53 has occurred."));
+
+        match UncConnection::connect_with(net.path().as_os_str(), &credentials()) {
+            Err(Error::UncConnection { message, .. }) => assert_eq!(message, "System error 53 has occurred."),
+            result => panic!("expected a connection error, got {:?}", result.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn finds_listed_shares() {
+        let connections = "\
+Status       Local     Remote                    Network
+
+-------------------------------------------------------------------------------
+OK           Z:        \\\\server\\data             Microsoft Windows Network
+OK                     \\\\Server\\Share            Microsoft Windows Network
+The command completed successfully.
+";
+
+        assert!(is_listed(connections, Path::new("\\\\server\\share")));
+        assert!(is_listed(connections, Path::new("\\\\server\\data")));
+        assert!(!is_listed(connections, Path::new("\\\\server\\sha")));
+        assert!(!is_listed(connections, Path::new("\\\\other\\share")));
+    }
+}
@@ -0,0 +1,59 @@
+//! Verification of copied files by comparing the hashes of their content
+//!
+//! Robocopy only compares sizes and timestamps, this module is enabled by the `verify` feature.
+
+use std::{fs::File, io, path::{Path, PathBuf}};
+
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::exit_codes::OkExitCode;
+
+/// The hash algorithm used to compare files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgo {
+    /// SHA-256
+    SHA256,
+    /// SHA-512
+    SHA512,
+}
+
+impl HashAlgo {
+    /// Hashes the content of the file, reading it by chunks so that large files aren't loaded in memory.
+    pub fn hash_file(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut file = File::open(path)?;
+        match self {
+            Self::SHA256 => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut file, &mut hasher)?;
+                Ok(hasher.finalize().to_vec())
+            },
+            Self::SHA512 => {
+                let mut hasher = Sha512::new();
+                io::copy(&mut file, &mut hasher)?;
+                Ok(hasher.finalize().to_vec())
+            }
+        }
+    }
+}
+
+/// The outcome of a verified copy
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// The exit code of robocopy
+    pub code: OkExitCode,
+    /// The number of files whose destination matches the source
+    pub verified: usize,
+    /// The destination files which differ from the source or are missing
+    pub mismatches: Vec<PathBuf>,
+}
+
+/// Returns true if the content of `destination` matches `source`, false if it differs or doesn't exist
+pub(crate) fn same_content(algo: HashAlgo, source: &Path, destination: &Path) -> io::Result<bool> {
+    let destination_hash = match algo.hash_file(destination) {
+        Ok(hash) => hash,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err)
+    };
+
+    Ok(algo.hash_file(source)? == destination_hash)
+}